@@ -0,0 +1,35 @@
+extern crate artifact;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, LogSink, OverflowPolicy};
+use artifact::level;
+
+/// Stands in for a slow disk.
+struct SlowSink;
+
+impl LogSink for SlowSink {
+  fn write(&mut self, _: &str, _: level::LogLevel, message: &str) {
+    thread::sleep(Duration::from_millis(2));
+    println!("{}", message);
+  }
+}
+
+fn main() {
+  let artifact_global = ArtifactGlobalLib::init_bounded(8, OverflowPolicy::DropBelow(level::WARNING));
+
+  let logger = Logger::new_with_level("Slow", LoggerOutput::Custom(Arc::new(Mutex::new(SlowSink))), level::VERBOSE);
+  for i in 0..50 {
+    if i % 10 == 0 {
+      logger.warning(&format!("Warnings always get through ({})", i));
+    } else {
+      logger.debug(&format!("Debug messages may be dropped ({})", i));
+    }
+  }
+
+  // Once the queue drains, the internal logger reports what was dropped.
+  artifact_global.flush();
+  logger.info("Done.");
+}
@@ -0,0 +1,22 @@
+extern crate artifact;
+
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::channel;
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, LogRecord, SimpleMessageFormatter};
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  let (tx, rx) = channel();
+  let logger = Logger::new("Foo", LoggerOutput::Channel(Arc::new(tx)));
+  logger.set_format(Box::new(SimpleMessageFormatter));
+
+  let callback_logger = Logger::new("Bar", LoggerOutput::Callback(Arc::new(Mutex::new(
+    |record: &LogRecord| println!("Callback got {} at level {}", record.message, record.level)))));
+
+  logger.warning("Delivered as a record.");
+  callback_logger.info("Delivered to a closure.");
+
+  let record = rx.recv().unwrap();
+  println!("{} ({}, {}): {}", record.logger, record.level_string, record.level, record.message);
+}
@@ -0,0 +1,23 @@
+extern crate artifact;
+
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, ColourFormatter, PatternFormatter};
+use artifact::format::SimpleMessageFormatter;
+use artifact::level;
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new_with_level("Foo", LoggerOutput::StdoutLog, level::VERBOSE);
+  logger.set_format(Box::new(ColourFormatter::new(SimpleMessageFormatter)));
+  logger.critical("Bold red.");
+  logger.severe("Red.");
+  logger.warning("Yellow.");
+  logger.info("Green.");
+  logger.debug("Cyan.");
+  logger.verbose("Dim.");
+
+  let padded = Logger::new("Padded", LoggerOutput::StderrLog);
+  padded.set_format(Box::new(ColourFormatter::new(PatternFormatter::new("{level:<8}| {message}").unwrap())));
+  padded.warning("Padding ignores the colour codes.");
+  padded.info("So these line up.");
+}
@@ -0,0 +1,39 @@
+extern crate artifact;
+
+use std::sync::{Arc, Mutex};
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, LogSink, LogLevel, SimpleMessageFormatter};
+use artifact::level;
+
+/// Collects messages into a vector shared with the rest of the program.
+struct CollectingSink{
+  collected: Arc<Mutex<Vec<String>>>
+}
+
+impl LogSink for CollectingSink {
+  fn write(&mut self, logger_name: &str, level: LogLevel, message: &str) {
+    if level <= level::WARNING {
+      self.collected.lock().unwrap().push(format!("{} had a problem: {}", logger_name, message));
+    }
+  }
+
+  fn close(&mut self) {
+    self.collected.lock().unwrap().push("Sink closed.".to_string());
+  }
+}
+
+fn main() {
+  let collected = Arc::new(Mutex::new(Vec::new()));
+
+  {
+    let _artifact_global = ArtifactGlobalLib::init();
+
+    let logger = Logger::new("Foo", LoggerOutput::Custom(Arc::new(Mutex::new(CollectingSink{collected: collected.clone()}))));
+    logger.set_format(Box::new(SimpleMessageFormatter));
+    logger.info("Not interesting enough to collect.");
+    logger.severe("Something went wrong.");
+  }
+
+  for message in collected.lock().unwrap().iter() {
+    println!("{}", message);
+  }
+}
@@ -1,7 +1,7 @@
 extern crate artifact;
 
 use std::path::PathBuf;
-use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, DEBUG, MessageFormatter};
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, DEBUG, MessageFormatter, FileOptions};
 
 struct FooFormat;
 
@@ -22,8 +22,10 @@ impl MessageFormatter for FooFormat {
 fn main() {
   let _artifact_global = ArtifactGlobalLib::init();
 
-  let logger = Logger::new("Foo", LoggerOutput::FileLog(PathBuf::from("foolog.log")));
-  let logger_two = Logger::new_with_level("Bar", LoggerOutput::FileLog(PathBuf::from("foolog.log")), DEBUG);
+  // Keep whatever earlier runs wrote, and make sure only the owner can read the log.
+  let options = FileOptions{append: true, create_dirs: true, mode: Some(0o600)};
+  let logger = Logger::new("Foo", LoggerOutput::FileLogWithOptions(PathBuf::from("logs/foolog.log"), options.clone()));
+  let logger_two = Logger::new_with_level("Bar", LoggerOutput::FileLogWithOptions(PathBuf::from("logs/foolog.log"), options), DEBUG);
   logger.set_format(Box::new(FooFormat) as Box<MessageFormatter>);
   logger.warning("This is a file log.");
   logger_two.debug("And we can have two loggers to one file at once.");
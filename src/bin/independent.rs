@@ -0,0 +1,34 @@
+extern crate artifact;
+
+use std::time::Duration;
+
+use artifact::{Logger, LoggerOutput, Artifact, ArtifactGlobalLib};
+
+fn main() {
+  // Each `Artifact` has a logger task and loggers of its own, so the
+  // two "Requests" loggers here don't clash.
+  let first = Artifact::new();
+  let second = Artifact::new();
+
+  let first_logger = Logger::new_in(&first, "Requests", LoggerOutput::StdoutLog);
+  let second_logger = Logger::new_in(&second, "Requests", LoggerOutput::StderrLog);
+  first_logger.info("Written to stdout by the first artifact.");
+  second_logger.info("Written to stderr by the second artifact.");
+
+  // Shutting one down leaves the other running.
+  println!("{:?}", first.shutdown(Duration::from_secs(1)));
+  second_logger.info("The second artifact is still running.");
+  drop(second);
+
+  // The global artifact can be started again once it's shut down.
+  // Threads which logged to the old one log to the new one, but the
+  // loggers have to be made again.
+  let global = ArtifactGlobalLib::init();
+  let logger = Logger::new("Global", LoggerOutput::StdoutLog);
+  logger.info("Logged by the first global artifact.");
+  drop(global);
+
+  let _global = ArtifactGlobalLib::init();
+  let logger = Logger::new("Global", LoggerOutput::StdoutLog);
+  logger.info("Logged by the second global artifact.");
+}
@@ -0,0 +1,17 @@
+extern crate artifact;
+
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, JsonMessageFormatter};
+use artifact::level;
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new("Foo", LoggerOutput::StdoutLog);
+  logger.set_format(Box::new(JsonMessageFormatter));
+  logger.info_kv("request done", &[("status", 200), ("ms", 14)]);
+  logger.warning("Quotes \" and backslashes \\ and\nnewlines are escaped.");
+
+  let multi = Logger::new_with_level("Multi", LoggerOutput::MultiLog(vec!("Foo".to_string())), level::WARNING);
+  multi.set_format(Box::new(JsonMessageFormatter));
+  multi.severe("Forwarding shows up as a field rather than a prefix.");
+}
@@ -0,0 +1,26 @@
+#[macro_use]
+extern crate artifact;
+
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib};
+use artifact::level;
+
+fn expensive(label: &str) -> String {
+  println!("  (formatting {})", label);
+  label.to_string()
+}
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new_with_level("Foo", LoggerOutput::StdoutLog, level::INFO);
+
+  // Only the messages at INFO or above get formatted.
+  logger.log_with(level::INFO, || expensive("info closure"));
+  logger.log_with(level::VERBOSE, || expensive("verbose closure"));
+  artifact_warning!(logger, "{}", expensive("warning macro"));
+  artifact_debug!(logger, "{}", expensive("debug macro"));
+
+  let multi = Logger::new("Multi", LoggerOutput::MultiLog(vec!("Foo".to_string())));
+  println!("Multi enabled at DEBUG: {}", multi.is_enabled(level::DEBUG));
+  println!("Multi enabled at INFO: {}", multi.is_enabled(level::INFO));
+}
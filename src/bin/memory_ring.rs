@@ -0,0 +1,19 @@
+extern crate artifact;
+
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, SimpleMessageFormatter};
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new("Foo", LoggerOutput::MemoryRing(3));
+  logger.set_format(Box::new(SimpleMessageFormatter));
+
+  for i in 0..5 {
+    logger.info(&format!("Message number {}", i));
+  }
+
+  // Only the last three messages are kept.
+  for message in logger.snapshot() {
+    println!("{}", message);
+  }
+}
@@ -0,0 +1,22 @@
+extern crate artifact;
+
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, PatternFormatter};
+use artifact::level;
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new("Foo", LoggerOutput::StdoutLog);
+  logger.set_format(Box::new(PatternFormatter::new("{time:%H:%M:%S} {level:<8} [{logger:^7}] {message} {fields}").unwrap()));
+  logger.info("Levels are padded so messages line up.");
+  logger.warning_kv("With fields", &[("status", 200)]);
+
+  let multi = Logger::new_with_level("Multi", LoggerOutput::MultiLog(vec!("Foo".to_string())), level::WARNING);
+  multi.set_format(Box::new(PatternFormatter::new("{level_value:>4} {logger} via {forwarded_from} ({thread}): {message}").unwrap()));
+  multi.severe("Forwarded through Multi.");
+
+  match PatternFormatter::new("{level} {nope}") {
+    Ok(_) => {}
+    Err(e) => logger.severe(&format!("{}", e))
+  }
+}
@@ -0,0 +1,20 @@
+extern crate artifact;
+
+use std::fs;
+use std::path::PathBuf;
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib};
+
+fn main() {
+  let artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new("Foo", LoggerOutput::FileLog(PathBuf::from("reopen.log")));
+  logger.info("This ends up in reopen.log.old");
+
+  // Simulate logrotate moving the file, then tell artifact about it.
+  // On Linux, reopen_files_on_sighup lets `kill -HUP` do the same.
+  std::thread::sleep(std::time::Duration::from_millis(100));
+  fs::rename("reopen.log", "reopen.log.old").unwrap();
+  artifact_global.reopen_files();
+
+  logger.info("This ends up in a fresh reopen.log");
+}
@@ -0,0 +1,18 @@
+extern crate artifact;
+
+use std::path::PathBuf;
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, FileOptions};
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  // Roll over every 256 bytes, keeping rotlog.log.1 through rotlog.log.3
+  let path = PathBuf::from("rotlog.log");
+  let logger = Logger::new("Foo", LoggerOutput::RotatingFileLog(path.clone(), 256, 3, FileOptions::default()));
+  let logger_two = Logger::new("Bar", LoggerOutput::RotatingFileLog(path, 256, 3, FileOptions::default()));
+
+  for i in 0..20 {
+    logger.info(&format!("Message number {} from Foo.", i));
+    logger_two.info(&format!("Message number {} from Bar, sharing the same rotated file.", i));
+  }
+}
@@ -0,0 +1,44 @@
+extern crate artifact;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, LogSink};
+use artifact::level;
+
+/// Stands in for a sink stuck on a dead network share.
+struct StuckSink;
+
+impl LogSink for StuckSink {
+  fn write(&mut self, _: &str, _: level::LogLevel, message: &str) {
+    println!("{}", message);
+  }
+
+  fn flush(&mut self) {
+    thread::sleep(Duration::from_secs(60));
+  }
+}
+
+fn main() {
+  let artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new("Main", LoggerOutput::StdoutLog);
+  let stuck = Logger::new("Stuck", LoggerOutput::Custom(Arc::new(Mutex::new(StuckSink))));
+
+  let worker = thread::spawn(|| {
+    let logger = Logger::access("Main");
+    for i in 0..5 {
+      logger.info(&format!("Message {} from the worker thread.", i));
+    }
+  });
+  worker.join().unwrap();
+
+  logger.info("Shutting down.");
+  stuck.info("This one gets written, but never flushed.");
+
+  // Everything sent above is written, but flushing the stuck sink
+  // never finishes, so shutdown gives up on it after a second.
+  let report = artifact_global.shutdown(Duration::from_secs(1));
+  println!("{:?}", report);
+}
@@ -0,0 +1,19 @@
+#[macro_use]
+extern crate artifact;
+
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, PatternFormatter, LogfmtMessageFormatter};
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new("Foo", LoggerOutput::StdoutLog);
+  logger.set_format(Box::new(PatternFormatter::new("{level:<8} {module}@{file}:{line} {message}").unwrap()));
+  let x = 42;
+  artifact_info!(logger, "x = {}", x);
+  artifact_warning!(logger, "Logged without arguments.");
+  logger.info("Plain methods don't record a location.");
+
+  let logfmt = Logger::new("Bar", LoggerOutput::StdoutLog);
+  logfmt.set_format(Box::new(LogfmtMessageFormatter));
+  artifact_severe!(logfmt, "{} went wrong", "something");
+}
@@ -0,0 +1,22 @@
+extern crate artifact;
+
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, FieldValue};
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  let logger = Logger::new("Foo", LoggerOutput::StdoutLog);
+  logger.info_kv("request done", &[("status", 200), ("ms", 14)]);
+  logger.warning_kv("slow request", &[("path", FieldValue::from("/index.html")), ("ms", FieldValue::from(2150))]);
+
+  // Outputs which deliver records keep the fields separate.
+  let (tx, rx) = channel();
+  let records = Logger::new("Bar", LoggerOutput::Channel(Arc::new(tx)));
+  records.info_kv("user logged in", &[("user", "brandon")]);
+  let record = rx.recv().unwrap();
+  for field in record.fields {
+    println!("{} = {}", field.key, field.value);
+  }
+}
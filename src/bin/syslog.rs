@@ -0,0 +1,31 @@
+extern crate artifact;
+
+use std::net::UdpSocket;
+use std::str;
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, SyslogOptions, SyslogTransport, SyslogFormat, SimpleMessageFormatter};
+
+fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  // Stand in for a syslog server, so this works without one installed.
+  let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+  let server_addr = server.local_addr().unwrap();
+
+  let options = SyslogOptions::new(SyslogTransport::Udp(server_addr), "syslog_example");
+  let logger = Logger::new("Foo", LoggerOutput::Syslog(options.clone()));
+  // Syslog records its own timestamp, no need for the default one.
+  logger.set_format(Box::new(SimpleMessageFormatter));
+
+  let bsd_logger = Logger::new("Bar", LoggerOutput::Syslog(SyslogOptions{format: SyslogFormat::Rfc3164, facility: 16, .. options}));
+  bsd_logger.set_format(Box::new(SimpleMessageFormatter));
+
+  logger.warning("A warning, in RFC 5424 format.");
+  logger.debug("A debug message.");
+  bsd_logger.critical("A critical message, in the older BSD format, from local0.");
+
+  let mut buf = [0u8; 2048];
+  for _ in 0..3 {
+    let len = server.recv(&mut buf).unwrap();
+    println!("{}", str::from_utf8(&buf[..len]).unwrap());
+  }
+}
@@ -0,0 +1,36 @@
+extern crate artifact;
+
+#[cfg(feature = "time")]
+fn main() {
+  with_time::main();
+}
+
+#[cfg(not(feature = "time"))]
+fn main() {
+  println!("Time feature not enabled.");
+}
+
+#[cfg(feature = "time")]
+mod with_time {
+
+use std::path::PathBuf;
+use std::time::Duration;
+use artifact::{Logger, LoggerOutput, ArtifactGlobalLib, RotationPeriod, FileOptions};
+
+pub fn main() {
+  let _artifact_global = ArtifactGlobalLib::init();
+
+  // One file per day, e.g. logs/timelog-2015-06-01.log.  Files from more
+  // than a week ago are removed whenever a new file is started.
+  let logger = Logger::new(
+    "Foo",
+    LoggerOutput::TimeRotatingFileLog(
+      PathBuf::from("logs/timelog-%Y-%m-%d.log"),
+      RotationPeriod::Daily,
+      Duration::from_secs(7 * 24 * 60 * 60),
+      FileOptions{create_dirs: true, .. FileOptions::default()}));
+
+  logger.info("This goes to today's log file.");
+}
+
+}
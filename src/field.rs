@@ -0,0 +1,110 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use std::fmt;
+
+/// A key-value pair attached to a log message, such as a status
+/// code or a duration.  Formatters and outputs which understand
+/// structured data can emit fields separately from the message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field{
+  pub key: String,
+  pub value: FieldValue,
+}
+
+/// The value of a `Field`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue{
+  Str(String),
+  Int(i64),
+  UInt(u64),
+  Float(f64),
+  Bool(bool),
+}
+
+impl Field{
+  pub fn new<V: Into<FieldValue>>(key: &str, value: V) -> Field {
+    Field{key: key.to_string(), value: value.into()}
+  }
+}
+
+impl fmt::Display for FieldValue {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      FieldValue::Str(ref s) => f.write_str(s),
+      FieldValue::Int(i) => write!(f, "{}", i),
+      FieldValue::UInt(u) => write!(f, "{}", u),
+      FieldValue::Float(v) => write!(f, "{}", v),
+      FieldValue::Bool(b) => write!(f, "{}", b),
+    }
+  }
+}
+
+macro_rules! field_value_from {
+  ($variant:ident, $target:ty, $($source:ty),+) => {
+    $(
+      impl From<$source> for FieldValue {
+        fn from(value: $source) -> FieldValue {
+          FieldValue::$variant(value as $target)
+        }
+      }
+    )+
+  }
+}
+
+field_value_from!(Int, i64, i8, i16, i32, i64, isize);
+field_value_from!(UInt, u64, u8, u16, u32, u64, usize);
+field_value_from!(Float, f64, f32, f64);
+
+impl From<bool> for FieldValue {
+  fn from(value: bool) -> FieldValue {
+    FieldValue::Bool(value)
+  }
+}
+
+impl<'a> From<&'a str> for FieldValue {
+  fn from(value: &'a str) -> FieldValue {
+    FieldValue::Str(value.to_string())
+  }
+}
+
+impl From<String> for FieldValue {
+  fn from(value: String) -> FieldValue {
+    FieldValue::Str(value)
+  }
+}
+
+/// Appends fields to a message as ` key=value` pairs.  String values
+/// which are empty or contain spaces, quotes or `=` are quoted.
+pub fn append_fields(message: &str, fields: &[Field]) -> String {
+  let mut full = message.to_string();
+  for field in fields {
+    match field.value {
+      FieldValue::Str(ref s) if s.is_empty() || s.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') =>
+        full.push_str(&format!(" {}={:?}", field.key, s)),
+      ref value =>
+        full.push_str(&format!(" {}={}", field.key, value)),
+    }
+  }
+  full
+}
@@ -24,16 +24,79 @@
 #[cfg(feature = "time")]
 use time;
 
+use std::env;
+
+use level;
+use level::LogLevel;
+use field;
+use field::{Field, FieldValue};
+use location::Location;
+
+/// Everything known about a message which is being formatted.
+pub struct MessageInfo<'a>{
+  /// The logger the message was logged to.
+  pub logger_name: &'a str,
+  pub level: LogLevel,
+  pub level_string: &'a str,
+  pub message: &'a str,
+  /// Fields attached to the message.  Empty for outputs which send
+  /// the fields on their own, such as syslog and journald.
+  pub fields: &'a [Field],
+  /// Name (or id, if it has no name) of the thread which logged the
+  /// message.  None for messages from Artifact's own logger task.
+  pub thread: Option<&'a str>,
+  /// How the message got from `logger_name` to the logger that will
+  /// write it, in order.  Empty if it was written directly.
+  pub forwarded: &'a [Forwarding<'a>],
+  /// Whether the message will be written to stdout or stderr, and that
+  /// stream is a terminal.
+  pub to_terminal: bool,
+  /// Where the message was logged from, if it was logged with one of
+  /// the `artifact_*!` macros.
+  pub location: Option<&'a Location>,
+}
+
+/// One step a message took before being written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Forwarding<'a>{
+  /// The multi-logger `from` passed the message on to `to`.
+  Multi{from: &'a str, to: &'a str},
+  /// The message was sent to `from`, which doesn't exist, so it went
+  /// to the default logger `to`.  Silent defaults don't show up here.
+  Default{from: &'a str, to: &'a str},
+}
+
 /// Trait for things that can format logging messages
 pub trait MessageFormatter : Send + Sync {
   /// Format a standard message.
   fn format_message(&self, logger_name:&str, level_string: &str, message: &str) -> String;
+
+  /// Format a message from all the information available about it.
+  /// This is what the logger task calls.  By default, any fields are
+  /// appended to the message as `key=value` pairs, and the result is
+  /// passed on to `format_message`.
+  fn format_message_info(&self, info: &MessageInfo) -> String {
+    if info.fields.is_empty() {
+      self.format_message(info.logger_name, info.level_string, info.message)
+    } else {
+      let message = field::append_fields(info.message, info.fields);
+      self.format_message(info.logger_name, info.level_string, &message)
+    }
+  }
   /// Format for messages being sent onward by a multi-logger.
   /// logger_name is the name of the next logger.  formatted_multi_msg is the
   /// message as formatted by the multi-logger's format_message method.
   fn add_logger_name_to_multi_message(&self, logger_name: &str, formatted_multi_msg:&str) -> String;
 
   fn add_defaulting_name_to_message(&self, default_logger_name: &str, formatted_msg:&str) -> String;
+
+  /// Whether `format_message_info` shows how the message was forwarded
+  /// itself, from `MessageInfo::forwarded`.  If so, the multi-loggers
+  /// and default logger it went through don't add anything to the
+  /// formatted message.
+  fn shows_forwarding(&self) -> bool {
+    false
+  }
 }
 
 /// Default formatter for logging messages
@@ -77,6 +140,258 @@ impl MessageFormatter for ZuluTimeMessageFormatter {
   }
 }
 
+/// Formats each message as a JSON object on a single line, for log
+/// pipelines which ingest JSON.  Includes a UTC timestamp if time info
+/// is available.  Forwarding by multi-loggers and the default logger is
+/// listed in a `forwarded` array instead of being added to the message.
+#[derive(Clone)]
+pub struct JsonMessageFormatter;
+
+impl MessageFormatter for JsonMessageFormatter {
+  fn format_message(&self, logger_name: &str, level_string: &str, message: &str) -> String {
+    let mut json = String::new();
+    json.push('{');
+    push_json_timestamp(&mut json);
+    push_json_key(&mut json, "logger");
+    push_json_string(&mut json, logger_name);
+    push_json_key(&mut json, "level");
+    push_json_string(&mut json, level_string);
+    push_json_key(&mut json, "message");
+    push_json_string(&mut json, message);
+    json.push('}');
+    json
+  }
+
+  fn format_message_info(&self, info: &MessageInfo) -> String {
+    let mut json = String::new();
+    json.push('{');
+    push_json_timestamp(&mut json);
+    push_json_key(&mut json, "logger");
+    push_json_string(&mut json, info.logger_name);
+    push_json_key(&mut json, "level");
+    push_json_string(&mut json, info.level_string);
+    push_json_key(&mut json, "level_value");
+    json.push_str(&info.level.to_string());
+    push_json_key(&mut json, "message");
+    push_json_string(&mut json, info.message);
+
+    if let Some(location) = info.location {
+      push_json_key(&mut json, "file");
+      push_json_string(&mut json, location.file);
+      push_json_key(&mut json, "line");
+      json.push_str(&location.line.to_string());
+      push_json_key(&mut json, "module");
+      push_json_string(&mut json, location.module_path);
+    }
+
+    if !info.fields.is_empty() {
+      push_json_key(&mut json, "fields");
+      json.push('{');
+      for field in info.fields {
+        push_json_key(&mut json, &field.key);
+        push_json_value(&mut json, &field.value);
+      }
+      json.push('}');
+    }
+
+    if !info.forwarded.is_empty() {
+      push_json_key(&mut json, "forwarded");
+      json.push('[');
+      for hop in info.forwarded {
+        let (kind, from, to) = match *hop {
+          Forwarding::Multi{from, to} => ("multi", from, to),
+          Forwarding::Default{from, to} => ("default", from, to),
+        };
+        if !json.ends_with('[') {
+          json.push(',');
+        }
+        json.push_str("{\"via\":");
+        push_json_string(&mut json, kind);
+        push_json_key(&mut json, "from");
+        push_json_string(&mut json, from);
+        push_json_key(&mut json, "to");
+        push_json_string(&mut json, to);
+        json.push('}');
+      }
+      json.push(']');
+    }
+
+    json.push('}');
+    json
+  }
+
+  fn add_logger_name_to_multi_message(&self, _: &str, msg: &str) -> String {
+    msg.to_string()
+  }
+
+  fn add_defaulting_name_to_message(&self, _: &str, msg: &str) -> String {
+    msg.to_string()
+  }
+
+  fn shows_forwarding(&self) -> bool {
+    true
+  }
+}
+
+#[cfg(feature = "time")]
+fn push_json_timestamp(json: &mut String) {
+  push_json_key(json, "timestamp");
+  push_json_string(json, &utc_timestamp());
+}
+
+#[cfg(not(feature = "time"))]
+fn push_json_timestamp(_: &mut String) {}
+
+/// Current UTC time in RFC 3339 format, with milliseconds.
+#[cfg(feature = "time")]
+fn utc_timestamp() -> String {
+  let now = time::now_utc();
+  format!("{}.{:03}Z",
+          time::strftime("%Y-%m-%dT%H:%M:%S", &now).unwrap_or_default(),
+          now.tm_nsec / 1_000_000)
+}
+
+/// Adds `"key":`, with a separating comma unless this is the first
+/// entry of an object.
+fn push_json_key(json: &mut String, key: &str) {
+  if !json.ends_with('{') {
+    json.push(',');
+  }
+  push_json_string(json, key);
+  json.push(':');
+}
+
+fn push_json_string(json: &mut String, value: &str) {
+  json.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => json.push_str("\\\""),
+      '\\' => json.push_str("\\\\"),
+      '\n' => json.push_str("\\n"),
+      '\r' => json.push_str("\\r"),
+      '\t' => json.push_str("\\t"),
+      c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+      c => json.push(c),
+    }
+  }
+  json.push('"');
+}
+
+fn push_json_value(json: &mut String, value: &FieldValue) {
+  match *value {
+    FieldValue::Str(ref s) => push_json_string(json, s),
+    // JSON has no way to write NaN or infinity.
+    FieldValue::Float(f) if !f.is_finite() => json.push_str("null"),
+    ref value => json.push_str(&value.to_string()),
+  }
+}
+
+/// Formats each message as a logfmt line, such as
+/// `ts=2015-06-01T12:00:00.000Z level=warning logger=Foo msg="Disk is full"`.
+/// Fields follow as extra keys.  A message forwarded by a multi-logger
+/// gets a `via=` key naming the multi-logger, and a message which ended
+/// up at the default logger gets a `defaulted_from=` key naming the
+/// logger it was sent to.  `logger` is the logger which wrote it.
+#[derive(Clone)]
+pub struct LogfmtMessageFormatter;
+
+impl MessageFormatter for LogfmtMessageFormatter {
+  fn format_message(&self, logger_name: &str, level_string: &str, message: &str) -> String {
+    let mut line = String::new();
+    push_logfmt_timestamp(&mut line);
+    push_logfmt_pair(&mut line, "level", &level_string.to_lowercase());
+    push_logfmt_pair(&mut line, "logger", logger_name);
+    push_logfmt_pair(&mut line, "msg", message);
+    line
+  }
+
+  fn format_message_info(&self, info: &MessageInfo) -> String {
+    let writing_logger = match info.forwarded.last() {
+      Some(&Forwarding::Multi{to, ..}) | Some(&Forwarding::Default{to, ..}) => to,
+      None => info.logger_name
+    };
+
+    let mut line = String::new();
+    push_logfmt_timestamp(&mut line);
+    push_logfmt_pair(&mut line, "level", &info.level_string.to_lowercase());
+    push_logfmt_pair(&mut line, "logger", writing_logger);
+    for hop in info.forwarded {
+      match *hop {
+        Forwarding::Multi{from, ..} => push_logfmt_pair(&mut line, "via", from),
+        Forwarding::Default{from, ..} => push_logfmt_pair(&mut line, "defaulted_from", from),
+      }
+    }
+    if let Some(location) = info.location {
+      push_logfmt_pair(&mut line, "caller", &format!("{}:{}", location.file, location.line));
+      push_logfmt_pair(&mut line, "module", location.module_path);
+    }
+    push_logfmt_pair(&mut line, "msg", info.message);
+    for field in info.fields {
+      push_logfmt_pair(&mut line, &field.key, &field.value.to_string());
+    }
+    line
+  }
+
+  fn add_logger_name_to_multi_message(&self, _: &str, msg: &str) -> String {
+    msg.to_string()
+  }
+
+  fn add_defaulting_name_to_message(&self, _: &str, msg: &str) -> String {
+    msg.to_string()
+  }
+
+  fn shows_forwarding(&self) -> bool {
+    true
+  }
+}
+
+#[cfg(feature = "time")]
+fn push_logfmt_timestamp(line: &mut String) {
+  push_logfmt_pair(line, "ts", &utc_timestamp());
+}
+
+#[cfg(not(feature = "time"))]
+fn push_logfmt_timestamp(_: &mut String) {}
+
+/// Adds ` key=value`.  Keys can't be quoted, so anything which would
+/// break them up is replaced with `_`.  Values are quoted and escaped if
+/// they're empty or contain spaces, quotes, `=` or control characters.
+fn push_logfmt_pair(line: &mut String, key: &str, value: &str) {
+  if !line.is_empty() {
+    line.push(' ');
+  }
+  for c in key.chars() {
+    if c.is_whitespace() || c.is_control() || c == '=' || c == '"' {
+      line.push('_');
+    } else {
+      line.push(c);
+    }
+  }
+  line.push('=');
+
+  let needs_quotes =
+    value.is_empty() ||
+    value.chars().any(|c| c.is_whitespace() || c.is_control() || c == '=' || c == '"');
+  if !needs_quotes {
+    line.push_str(value);
+    return;
+  }
+
+  line.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => line.push_str("\\\""),
+      '\\' => line.push_str("\\\\"),
+      '\n' => line.push_str("\\n"),
+      '\r' => line.push_str("\\r"),
+      '\t' => line.push_str("\\t"),
+      c if c.is_control() => line.push_str(&format!("\\u{:04x}", c as u32)),
+      c => line.push(c),
+    }
+  }
+  line.push('"');
+}
+
 #[cfg(not(feature = "time"))]
 pub fn new_basic_format_instance() -> Box<MessageFormatter> {
   Box::new(SimpleMessageFormatter)
@@ -93,10 +408,171 @@ impl<F> MessageFormatter for NoForwardingIndicationFormatter<F> where F: Message
   fn format_message(&self, logger_name: &str, level_string: &str, message: &str) -> String {
     self.0.format_message(logger_name, level_string, message)
   }
+  fn format_message_info(&self, info: &MessageInfo) -> String {
+    self.0.format_message_info(info)
+  }
   fn add_logger_name_to_multi_message(&self, _: &str, msg: &str) -> String {
     msg.to_string()
   }
   fn add_defaulting_name_to_message(&self, _: &str, msg: &str) -> String {
     msg.to_string()
   }
+  fn shows_forwarding(&self) -> bool {
+    self.0.shows_forwarding()
+  }
+}
+
+/// Colours the level string by severity with ANSI escape codes, then
+/// formats the message with the wrapped formatter.  Nothing is coloured
+/// unless the message is going to a terminal, or if the `NO_COLOR`
+/// environment variable was set when the formatter was created.
+pub struct ColourFormatter<T> where T: MessageFormatter{
+  inner: T,
+  enabled: bool,
+}
+
+impl<F> ColourFormatter<F> where F: MessageFormatter{
+  pub fn new(inner: F) -> ColourFormatter<F> {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    ColourFormatter{inner, enabled: !no_color}
+  }
+}
+
+fn level_colour(level: LogLevel) -> &'static str {
+  if level <= level::CRITICAL {
+    "1;31"
+  } else if level <= level::SEVERE {
+    "31"
+  } else if level <= level::WARNING {
+    "33"
+  } else if level <= level::INFO {
+    "32"
+  } else if level <= level::DEBUG {
+    "36"
+  } else {
+    "2"
+  }
+}
+
+impl<F> MessageFormatter for ColourFormatter<F> where F: MessageFormatter{
+  fn format_message(&self, logger_name: &str, level_string: &str, message: &str) -> String {
+    // Without the level or the destination there's nothing to go on.
+    self.inner.format_message(logger_name, level_string, message)
+  }
+  fn format_message_info(&self, info: &MessageInfo) -> String {
+    if !self.enabled || !info.to_terminal {
+      return self.inner.format_message_info(info);
+    }
+    let coloured = format!("\x1b[{}m{}\x1b[0m", level_colour(info.level), info.level_string);
+    self.inner.format_message_info(&MessageInfo{level_string: &coloured, ..*info})
+  }
+  fn add_logger_name_to_multi_message(&self, logger_name: &str, formatted_msg: &str) -> String {
+    self.inner.add_logger_name_to_multi_message(logger_name, formatted_msg)
+  }
+  fn add_defaulting_name_to_message(&self, default_logger_name: &str, formatted_msg: &str) -> String {
+    self.inner.add_defaulting_name_to_message(default_logger_name, formatted_msg)
+  }
+  fn shows_forwarding(&self) -> bool {
+    self.inner.shows_forwarding()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use field::{Field, FieldValue};
+  use level;
+  use super::*;
+
+  fn info<'a>(message: &'a str, fields: &'a [Field], forwarded: &'a [Forwarding<'a>]) -> MessageInfo<'a> {
+    MessageInfo{
+      logger_name: "Foo",
+      level: level::WARNING,
+      level_string: "WARNING",
+      message,
+      fields,
+      thread: None,
+      forwarded,
+      to_terminal: false,
+      location: None}
+  }
+
+  /// Drops the timestamp, if there is one, so the rest can be compared.
+  fn without_timestamp(json: &str) -> String {
+    match json.find("\"logger\"") {
+      Some(start) => format!("{{{}", &json[start..]),
+      None => json.to_string()
+    }
+  }
+
+  #[test]
+  fn json_strings_are_escaped() {
+    let mut json = String::new();
+    push_json_string(&mut json, "a \"quote\", a \\ and\na\tb\r\u{1}é");
+    assert_eq!(json, r#""a \"quote\", a \\ and\na\tb\r\u0001é""#);
+  }
+
+  #[test]
+  fn json_values_are_typed() {
+    let mut json = String::new();
+    for value in &[FieldValue::Int(-3), FieldValue::UInt(4), FieldValue::Bool(true),
+                   FieldValue::Float(1.5), FieldValue::Float(f64::NAN), FieldValue::Str("x".to_string())] {
+      push_json_value(&mut json, value);
+      json.push(' ');
+    }
+    assert_eq!(json, r#"-3 4 true 1.5 null "x" "#);
+  }
+
+  #[test]
+  fn json_message_with_fields_and_forwarding() {
+    let fields = vec![Field::new("user \"id\"", 7), Field::new("ok", false)];
+    let forwarded = [Forwarding::Default{from: "Missing", to: "Foo"}];
+    let json = JsonMessageFormatter.format_message_info(&info("line\nbreak", &fields, &forwarded));
+
+    assert_eq!(
+      without_timestamp(&json),
+      concat!(r#"{"logger":"Foo","level":"WARNING","level_value":400,"message":"line\nbreak","#,
+              r#""fields":{"user \"id\"":7,"ok":false},"#,
+              r#""forwarded":[{"via":"default","from":"Missing","to":"Foo"}]}"#));
+  }
+
+  #[test]
+  fn route_aware_formatters_show_forwarding() {
+    assert!(JsonMessageFormatter.shows_forwarding());
+    assert!(NoForwardingIndicationFormatter(JsonMessageFormatter).shows_forwarding());
+    assert!(!SimpleMessageFormatter.shows_forwarding());
+  }
+
+  /// Drops the `ts=` pair, if there is one.
+  fn without_ts(line: &str) -> &str {
+    match line.find("level=") {
+      Some(start) => &line[start..],
+      None => line
+    }
+  }
+
+  #[test]
+  fn logfmt_values_are_quoted_when_needed() {
+    let mut line = String::new();
+    push_logfmt_pair(&mut line, "plain", "value");
+    push_logfmt_pair(&mut line, "empty", "");
+    push_logfmt_pair(&mut line, "spaced", "two words");
+    push_logfmt_pair(&mut line, "tricky", "a=\"b\"\\\n\u{7}");
+    assert_eq!(line, r#"plain=value empty="" spaced="two words" tricky="a=\"b\"\\\n\u0007""#);
+  }
+
+  #[test]
+  fn logfmt_keys_are_never_quoted() {
+    let mut line = String::new();
+    push_logfmt_pair(&mut line, "a key=\"x\"\n", "1");
+    assert_eq!(line, "a_key__x__=1");
+  }
+
+  #[test]
+  fn logfmt_message_with_fields_and_forwarding() {
+    let fields = vec![Field::new("status", 200), Field::new("path", "/a b")];
+    let forwarded = [Forwarding::Multi{from: "Foo", to: "Bar"}];
+    let line = LogfmtMessageFormatter.format_message_info(&info("all done", &fields, &forwarded));
+
+    assert_eq!(without_ts(&line), r#"level=warning logger=Bar via=Foo msg="all done" status=200 path="/a b""#);
+  }
 }
@@ -21,27 +21,163 @@
  *
  */
 
-use std::sync::Mutex;
-use std::sync::mpsc::{Sender, channel};
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
 use std::cell::RefCell;
 
-use std::thread::JoinHandle;
-
 use internal::task;
+use internal::levels::{LevelCache, LevelSnapshot};
+use internal::queue::MessageQueue;
 use level;
 
 lazy_static!(
-  static ref GLOBAL_LOGGER_ACCESS: Mutex<Option<Artifact>> = Mutex::new(None);
+  static ref GLOBAL_LOGGER_ACCESS: Mutex<Option<Connection>> = Mutex::new(None);
 );
 
-thread_local!(static LOCAL_LOGGER_CELL: RefCell<Option<Artifact>> = RefCell::new(None));
+/// Changed whenever the global task is started or stopped, so that
+/// threads can tell their copy of the global `Connection` is stale.
+static GLOBAL_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local!(static LOCAL_LOGGER_CELL: RefCell<Option<LocalArtifact>> = RefCell::new(None));
+
+// For each `Connection` this thread has logged through, the level
+// snapshot it last filtered messages with.  Held by a `Weak` so that a
+// new cache can't take over the address of one which has gone away.
+thread_local!(static LOCAL_LEVELS: RefCell<Vec<LocalLevels>> = const { RefCell::new(Vec::new()) });
+
+type LocalLevels = (Weak<LevelCache>, Option<Arc<LevelSnapshot>>);
+
+/// How messages get to one logger task.
+#[derive(Clone)]
+pub struct Connection{
+  backend: Backend,
+  level_cache: Arc<LevelCache>,
+}
 
+/// Where messages for the logger task go.
 #[derive(Clone)]
-pub struct Artifact{
-  msg_tx: Sender<task::LoggerMessage>
+enum Backend{
+  /// To the logger thread.
+  Thread(Arc<MessageQueue>),
+  /// Straight to the task, on the sending thread.
+  Inline(Arc<task::InlineLogger>),
 }
 
-pub fn init_global_task() -> Option<JoinHandle<()>> {
+/// A thread's copy of the global `Connection`, along with the level
+/// snapshot it last filtered messages with.
+struct LocalArtifact{
+  connection: Connection,
+  levels: Option<Arc<LevelSnapshot>>,
+  generation: usize,
+}
+
+impl LocalArtifact{
+  fn accepts(&mut self, logger_name: &str, level: level::LogLevel) -> Option<bool> {
+    self.connection.level_cache.accepts(&mut self.levels, logger_name, level)
+  }
+}
+
+impl Connection{
+  /// Sends a message to the logger task.
+  pub fn send(&self, message: task::LoggerMessage) {
+    match message {
+      task::LoggerMessage::NewLogger(..) |
+      task::LoggerMessage::RedirectLogger(..) |
+      task::LoggerMessage::Disable(..) |
+      task::LoggerMessage::SetDefaultLogTarget(..) =>
+        self.level_cache.begin_update(),
+      _ => {}
+    }
+    send_to_logger(&self.backend, message);
+  }
+
+  /// As `send_log_entry`, for the task this connection goes to.
+  pub fn send_log_entry<F>(&self, logger_name: &str, level: level::LogLevel, make_entry: F)
+      where F: FnOnce() -> task::LogEntry {
+    if self.accepts(logger_name, level) != Some(false) {
+      self.send(task::LoggerMessage::LogMessage(make_entry()));
+    }
+  }
+
+  /// As `is_log_enabled`, for the task this connection goes to.
+  pub fn is_log_enabled(&self, logger_name: &str, level: level::LogLevel) -> bool {
+    let cached = self.accepts(logger_name, level);
+    cached.unwrap_or_else(|| self.ask_is_log_enabled(logger_name, level))
+  }
+
+  /// Checks the level cache with this thread's snapshot for it, as
+  /// `LocalArtifact` does for the global connection.
+  fn accepts(&self, logger_name: &str, level: level::LogLevel) -> Option<bool> {
+    LOCAL_LEVELS.with(|levels| {
+      let mut levels = levels.borrow_mut();
+      let cache = Arc::as_ptr(&self.level_cache);
+      let index = match levels.iter().position(|(key, _)| Weak::as_ptr(key) == cache) {
+        Some(index) => index,
+        None => {
+          // Snapshots for tasks which have since shut down are dropped
+          // along the way.
+          levels.retain(|(key, _)| key.strong_count() != 0);
+          levels.push((Arc::downgrade(&self.level_cache), None));
+          levels.len() - 1
+        }
+      };
+      self.level_cache.accepts(&mut levels[index].1, logger_name, level)
+    })
+  }
+
+  fn ask_is_log_enabled(&self, logger_name: &str, level: level::LogLevel) -> bool {
+    let (tx, rx) = channel();
+    self.send(task::LoggerMessage::IsLogEnabled(logger_name.to_string(), level, tx));
+    rx.recv().unwrap_or(false)
+  }
+
+  fn register_level(&self, name: &str, level: level::LogLevel) {
+    self.send(task::LoggerMessage::RegisterLevelString(level, name.to_string()));
+  }
+}
+
+/// Loggers compare equal if they use the same logger task.
+impl PartialEq for Connection{
+  fn eq(&self, other: &Connection) -> bool {
+    Arc::ptr_eq(&self.level_cache, &other.level_cache)
+  }
+}
+
+impl Eq for Connection{}
+
+impl fmt::Debug for Connection{
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mode = match self.backend {
+      Backend::Thread(_) => "thread",
+      Backend::Inline(_) => "inline",
+    };
+    write!(f, "Connection({})", mode)
+  }
+}
+
+/// Starts a logger task, either on its own thread fed by the given
+/// queue or, if there's no queue, to be run by the threads which log.
+pub fn start_task(queue: Option<MessageQueue>) -> (Connection, Option<task::TaskHandle>) {
+  let (connection, handle) = match queue {
+    Some(queue) => spawn_logger_task(queue),
+    None => start_inline_task()
+  };
+  connection.register_level("TERRIBLE FAILURE", level::WTF);
+  connection.register_level("CRITICAL", level::CRITICAL);
+  connection.register_level("SEVERE", level::SEVERE);
+  connection.register_level("WARNING", level::WARNING);
+  connection.register_level("DEBUG", level::DEBUG);
+  connection.register_level("INFO", level::INFO);
+  connection.register_level("TRACE", level::TRACE);
+  connection.register_level("VERBOSE", level::VERBOSE);
+  (connection, Some(handle))
+}
+
+/// Starts the logger task which loggers without an `Artifact` of their
+/// own use.  Returns None if it's already running.
+pub fn init_global_task(queue: Option<MessageQueue>) -> Option<(Connection, Option<task::TaskHandle>)> {
   let g_logger_res = GLOBAL_LOGGER_ACCESS.lock();
 
   if g_logger_res.is_err() {
@@ -52,78 +188,128 @@ pub fn init_global_task() -> Option<JoinHandle<()>> {
   let mut g_logger = g_logger_res.unwrap();
 
   if g_logger.is_none() {
-    let (logger_task_sender, guard) = spawn_logger_task();
-    register_level(&logger_task_sender, "TERRIBLE FAILURE", level::WTF);
-    register_level(&logger_task_sender, "CRITICAL", level::CRITICAL);
-    register_level(&logger_task_sender, "SEVERE", level::SEVERE);
-    register_level(&logger_task_sender, "WARNING", level::WARNING);
-    register_level(&logger_task_sender, "DEBUG", level::DEBUG);
-    register_level(&logger_task_sender, "INFO", level::INFO);
-    register_level(&logger_task_sender, "TRACE", level::TRACE);
-    register_level(&logger_task_sender, "VERBOSE", level::VERBOSE);
-
-    *g_logger = Some(logger_task_sender);
-    Some(guard)
+    let (connection, handle) = start_task(queue);
+    *g_logger = Some(connection.clone());
+    GLOBAL_GENERATION.fetch_add(1, Ordering::SeqCst);
+    Some((connection, handle))
   } else {
     None
   }
 }
 
-fn register_level(artifact_state: &Artifact,
-                  name: &str,
-                  level: level::LogLevel) {
-  //TODO proper error handling
-  let _ = artifact_state.msg_tx.send(task::LoggerMessage::RegisterLevelString(level, name.to_string()));
+/// Forgets the global logger task, so that it can be started again.
+/// Stopping it is up to whoever started it.
+pub fn clear_global_task() {
+  let mut g_logger = match GLOBAL_LOGGER_ACCESS.lock() {
+    Ok(g_logger) => g_logger,
+    Err(poisoned) => poisoned.into_inner()
+  };
+  *g_logger = None;
+  GLOBAL_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn send_logger_message(message: task::LoggerMessage){
+  // Sent outside of the thread local, as in synchronous mode sinks can
+  // log while the message is handled.
+  if let Some(connection) = with_local_artifact(|local| local.connection.clone()) {
+    connection.send(message);
+  }
+}
+
+/// Builds and sends a log message, unless the level cache shows the
+/// logger task would drop it, in which case nothing is built at all.
+pub fn send_log_entry<F>(logger_name: &str, level: level::LogLevel, make_entry: F)
+    where F: FnOnce() -> task::LogEntry {
+  // Decided before building the entry, as that can run Display impls
+  // which log themselves.
+  let connection = with_local_artifact(|local| {
+    match local.accepts(logger_name, level) {
+      Some(false) => None,
+      _ => Some(local.connection.clone())
+    }
+  });
+  if let Some(Some(connection)) = connection {
+    connection.send(task::LoggerMessage::LogMessage(make_entry()));
+  }
 }
 
-pub fn stop_global_task(){
-  //TODO proper error handling
-  match *GLOBAL_LOGGER_ACCESS.lock().unwrap() {
-    Some(Artifact{ref msg_tx}) => { let _ = msg_tx.send(task::LoggerMessage::PoisonPill); }
-    None => {}
+/// Whether a message at the given level sent to the logger would be
+/// written.  Answered from the level cache if it's up to date, and by
+/// asking the logger task otherwise.
+pub fn is_log_enabled(logger_name: &str, level: level::LogLevel) -> bool {
+  let cached = with_local_artifact(|local| {
+    local.accepts(logger_name, level).ok_or_else(|| local.connection.clone())
+  });
+  match cached {
+    Some(Ok(enabled)) => enabled,
+    Some(Err(connection)) => connection.ask_is_log_enabled(logger_name, level),
+    None => false
   }
 }
 
-pub fn send_logger_message(message: task::LoggerMessage){
-  LOCAL_LOGGER_CELL.with(|logger_cell:&RefCell<Option<Artifact>>| {
+/// Runs `f` with this thread's `LocalArtifact`, setting it up first if
+/// needed or if the global task has been restarted since.  Returns None
+/// if Artifact isn't initialized.
+fn with_local_artifact<F, R>(f: F) -> Option<R> where F: FnOnce(&mut LocalArtifact) -> R {
+  LOCAL_LOGGER_CELL.with(|logger_cell:&RefCell<Option<LocalArtifact>>| {
     let mut mut_cell_internal = logger_cell.borrow_mut();
-    let tls_initialized = mut_cell_internal.is_some();
 
-    if tls_initialized {
-      send_to_logger(&mut_cell_internal.as_ref().unwrap().msg_tx, message)
-    } else {
-      send_logger_message_with_uninit_tls(&mut *mut_cell_internal, message)
+    let generation = GLOBAL_GENERATION.load(Ordering::SeqCst);
+    let is_current = match *mut_cell_internal {
+      Some(ref local) => local.generation == generation,
+      None => false
+    };
+    if !is_current {
+      *mut_cell_internal = init_local_artifact();
     }
+    mut_cell_internal.as_mut().map(f)
   })
 }
 
-fn send_logger_message_with_uninit_tls(tls_ref:&mut Option<Artifact>, message: task::LoggerMessage){
+fn init_local_artifact() -> Option<LocalArtifact> {
   let local_sender_opt = GLOBAL_LOGGER_ACCESS.lock();
 
   if local_sender_opt.is_err() {
     if !cfg!(feature = "no-failure-logs") {
       println!("Global logger mutex is corrupted.  Cannot init task-local sender instance.");
     }
-    return;
+    return None;
   }
 
-  if let Some(ref local_sender) = *local_sender_opt.unwrap() {
-    send_to_logger(&local_sender.msg_tx, message);
-    *tls_ref = Some(local_sender.clone());
-  } else if !cfg!(feature = "no-failure-logs") {
-    println!("Global artifact logger not initialized.");
+  match *local_sender_opt.unwrap() {
+    Some(ref local_sender) =>
+      Some(LocalArtifact{
+        connection: local_sender.clone(),
+        levels: None,
+        generation: GLOBAL_GENERATION.load(Ordering::SeqCst)}),
+    None => {
+      if !cfg!(feature = "no-failure-logs") {
+        println!("Global artifact logger not initialized.");
+      }
+      None
+    }
   }
 }
 
-fn send_to_logger(logger:&Sender<task::LoggerMessage>, message: task::LoggerMessage){
-  match logger.send(message) {
-    Err(_) if cfg!(not(feature = "no-failure-logs")) => println!("Logger task is down, could not send message."),
-    _ => {}
+fn send_to_logger(backend: &Backend, message: task::LoggerMessage){
+  let sent = match *backend {
+    Backend::Thread(ref queue) => queue.push(message),
+    Backend::Inline(ref logger) => logger.handle(message),
+  };
+  if !sent && cfg!(not(feature = "no-failure-logs")) {
+    println!("Logger task is down, could not send message.");
   }
 }
 
-fn spawn_logger_task() -> (Artifact, JoinHandle<()>) {
-  let (tx, rx) = channel();
-  let thread_guard = task::spawn_logger(rx);
-  (Artifact{msg_tx: tx}, thread_guard)
+fn spawn_logger_task(queue: MessageQueue) -> (Connection, task::TaskHandle) {
+  let queue = Arc::new(queue);
+  let level_cache = Arc::new(LevelCache::new());
+  let handle = task::spawn_logger(queue.clone(), level_cache.clone());
+  (Connection{backend: Backend::Thread(queue), level_cache}, handle)
+}
+
+fn start_inline_task() -> (Connection, task::TaskHandle) {
+  let level_cache = Arc::new(LevelCache::new());
+  let (logger, handle) = task::start_inline(level_cache.clone());
+  (Connection{backend: Backend::Inline(logger), level_cache}, handle)
 }
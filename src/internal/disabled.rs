@@ -0,0 +1,69 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+//! Stands in for `comm` when the `disabled` feature is on.  No logger
+//! task is started and everything sent to it is dropped on the spot.
+
+use internal::task;
+use internal::queue::MessageQueue;
+use level;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Connection;
+
+impl Connection{
+  #[inline(always)]
+  pub fn send(&self, _: task::LoggerMessage) {}
+
+  #[inline(always)]
+  pub fn send_log_entry<F>(&self, _: &str, _: level::LogLevel, _: F) where F: FnOnce() -> task::LogEntry {}
+
+  #[inline(always)]
+  pub fn is_log_enabled(&self, _: &str, _: level::LogLevel) -> bool {
+    false
+  }
+}
+
+#[inline(always)]
+pub fn start_task(_: Option<MessageQueue>) -> (Connection, Option<task::TaskHandle>) {
+  (Connection, None)
+}
+
+#[inline(always)]
+pub fn init_global_task(_: Option<MessageQueue>) -> Option<(Connection, Option<task::TaskHandle>)> {
+  None
+}
+
+#[inline(always)]
+pub fn clear_global_task() {}
+
+#[inline(always)]
+pub fn send_logger_message(_: task::LoggerMessage){}
+
+#[inline(always)]
+pub fn send_log_entry<F>(_: &str, _: level::LogLevel, _: F) where F: FnOnce() -> task::LogEntry {}
+
+#[inline(always)]
+pub fn is_log_enabled(_: &str, _: level::LogLevel) -> bool {
+  false
+}
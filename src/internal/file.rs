@@ -0,0 +1,480 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use logger::FileOptions;
+
+#[cfg(feature = "time")]
+use std::time::Duration;
+#[cfg(feature = "time")]
+use time;
+
+#[cfg(feature = "time")]
+use logger::RotationPeriod;
+
+/// Decides when a log file gets moved out of the way.
+enum Rotation{
+  Never,
+  /// Maximum size in bytes, number of old files to keep.
+  BySize(u64, usize),
+  /// Path pattern, period, maximum age of old files, and the
+  /// time (in seconds since the epoch) of the next switch.
+  #[cfg(feature = "time")]
+  ByTime(PathBuf, RotationPeriod, Duration, i64),
+}
+
+/// A file being written to by one or more file loggers.
+pub struct LogFile{
+  path: PathBuf,
+  file: File,
+  rotation: Rotation,
+  size: u64,
+  options: FileOptions,
+}
+
+impl LogFile{
+  /// Opens the file at the given path, truncating or appending to it
+  /// as the options say.
+  pub fn open(path: PathBuf, options: &FileOptions) -> io::Result<LogFile> {
+    let file =
+      if options.append {
+        open_for_append(&path, options)?
+      } else {
+        create_parent_dirs(&path, options)?;
+        let mut open_options = OpenOptions::new();
+        open_options.write(true).create(true).truncate(true);
+        set_mode(&mut open_options, options.mode);
+        open_options.open(&path)?
+      };
+    let size = file.metadata()?.len();
+    Ok(LogFile{path, file, rotation: Rotation::Never, size, options: options.clone()})
+  }
+
+  /// Opens the file at the given path for appending, rotating it
+  /// whenever it would grow past `max_size` bytes.  The `append`
+  /// option is ignored, as an existing file is always appended to.
+  pub fn open_rotating(path: PathBuf, max_size: u64, keep: usize, options: &FileOptions) -> io::Result<LogFile> {
+    let file = open_for_append(&path, options)?;
+    let size = file.metadata()?.len();
+    Ok(LogFile{path, file, rotation: Rotation::BySize(max_size, keep), size, options: options.clone()})
+  }
+
+  /// Opens the file named by formatting the given pattern with the
+  /// current UTC time, switching to a new file at every period boundary.
+  /// Files in the same directory whose names match the pattern and which
+  /// are older than `max_age` are removed.
+  #[cfg(feature = "time")]
+  pub fn open_time_rotating(pattern: PathBuf, period: RotationPeriod, max_age: Duration, options: &FileOptions)
+      -> io::Result<LogFile> {
+    let now = time::now_utc();
+    let path = expand_pattern(&pattern, &now)?;
+    let file = open_for_append(&path, options)?;
+    let next_switch = next_period_start(now.to_timespec().sec, period);
+    let log_file =
+      LogFile{
+        path,
+        file,
+        rotation: Rotation::ByTime(pattern, period, max_age, next_switch),
+        size: 0,
+        options: options.clone()};
+    log_file.prune()?;
+    Ok(log_file)
+  }
+
+  /// Writes a line, rotating the file first if it's due.  If rotating
+  /// fails the line is still written, to whichever file is open after,
+  /// and the error is returned afterwards.
+  pub fn write_line(&mut self, message: &str) -> io::Result<()> {
+    let line_len = message.len() as u64 + 1;
+    let mut rotate_error = None;
+
+    match self.rotation {
+      Rotation::Never => {}
+      Rotation::BySize(max_size, keep) => {
+        // A single line larger than the limit still gets written, it just
+        // ends up in a file on its own.
+        if self.size > 0 && self.size + line_len > max_size {
+          if let Err(e) = self.rotate(keep) {
+            // Carry on at the path, creating the file if it's gone.  The
+            // size starts over either way, so the next try is another
+            // `max_size` bytes away rather than on every line.
+            let _ = self.reopen();
+            self.size = 0;
+            rotate_error = Some(e);
+          }
+        }
+      }
+      #[cfg(feature = "time")]
+      Rotation::ByTime(_, _, _, next_switch) => {
+        if time::get_time().sec >= next_switch {
+          if let Err(e) = self.switch_period() {
+            rotate_error = Some(e);
+          }
+        }
+      }
+    }
+
+    writeln!(self.file, "{}", message)?;
+    self.size += line_len;
+    match rotate_error {
+      Some(e) => Err(e),
+      None => Ok(())
+    }
+  }
+
+  /// Closes the file and opens whatever is now at its path, creating
+  /// it if needed.  The new file is always appended to, since truncating
+  /// here would throw away anything written since the file was moved.
+  pub fn reopen(&mut self) -> io::Result<()> {
+    self.file = open_for_append(&self.path, &self.options)?;
+    self.size = self.file.metadata()?.len();
+    Ok(())
+  }
+
+  /// Flushes the file and, if `sync` is set, waits for it to reach the disk.
+  pub fn flush(&mut self, sync: bool) -> io::Result<()> {
+    self.file.flush()?;
+    if sync {
+      self.file.sync_data()?;
+    }
+    Ok(())
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Whether the file is ever moved aside for a new one.
+  pub fn rotates(&self) -> bool {
+    !matches!(self.rotation, Rotation::Never)
+  }
+
+  /// Shifts `path.N-1` to `path.N` and so on down to `path` to `path.1`,
+  /// then starts a fresh file at `path`.  Whatever was at `path.N` is lost.
+  fn rotate(&mut self, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+      self.file.set_len(0)?;
+    } else {
+      for idx in (1..keep).rev() {
+        if let Err(e) = fs::rename(numbered_path(&self.path, idx), numbered_path(&self.path, idx + 1)) {
+          if e.kind() != io::ErrorKind::NotFound {
+            return Err(e);
+          }
+        }
+      }
+      fs::rename(&self.path, numbered_path(&self.path, 1))?;
+      self.file = open_for_append(&self.path, &self.options)?;
+    }
+    self.size = 0;
+    Ok(())
+  }
+}
+
+#[cfg(feature = "time")]
+impl LogFile{
+  /// Moves on to the file for the current period.  If the new file
+  /// can't be opened the old one stays in use until the next period
+  /// starts; pruning only happens once the new file is open.
+  fn switch_period(&mut self) -> io::Result<()> {
+    let now = time::now_utc();
+    if let Rotation::ByTime(ref pattern, period, _, ref mut next_switch) = self.rotation {
+      *next_switch = next_period_start(now.to_timespec().sec, period);
+      let path = expand_pattern(pattern, &now)?;
+      self.file = open_for_append(&path, &self.options)?;
+      self.path = path;
+    }
+    self.prune()
+  }
+
+  /// Removes old files matching the pattern.  Only the file name part
+  /// of the pattern is considered, the directory must be fixed.
+  fn prune(&self) -> io::Result<()> {
+    if let Rotation::ByTime(ref pattern, period, max_age, _) = self.rotation {
+      let name_pattern = match pattern.file_name().and_then(|n| n.to_str()) {
+        Some(name_pattern) => name_pattern,
+        None => return Ok(())
+      };
+      let dir = match pattern.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+      };
+      let cutoff = time::get_time().sec - max_age.as_secs() as i64;
+
+      for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = match name.to_str() {
+          Some(name) => name,
+          None => continue
+        };
+        let started = match time::strptime(name, name_pattern) {
+          // strptime ignores trailing input, so make sure the name
+          // really is one this pattern produces.
+          Ok(ref tm) if time::strftime(name_pattern, tm).ok().as_ref().map(|s| &s[..]) == Some(name) =>
+            tm.to_timespec().sec,
+          _ => continue
+        };
+        let path = entry.path();
+        if started + period.seconds() <= cutoff && path != self.path {
+          let _ = fs::remove_file(path);
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(feature = "time")]
+fn expand_pattern(pattern: &Path, now: &time::Tm) -> io::Result<PathBuf> {
+  match pattern.to_str().map(|p| time::strftime(p, now)) {
+    Some(Ok(expanded)) => Ok(PathBuf::from(expanded)),
+    _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid log file name pattern"))
+  }
+}
+
+#[cfg(feature = "time")]
+fn next_period_start(now_sec: i64, period: RotationPeriod) -> i64 {
+  let length = period.seconds();
+  (now_sec / length + 1) * length
+}
+
+fn open_for_append(path: &Path, options: &FileOptions) -> io::Result<File> {
+  create_parent_dirs(path, options)?;
+  let mut open_options = OpenOptions::new();
+  open_options.append(true).create(true);
+  set_mode(&mut open_options, options.mode);
+  open_options.open(path)
+}
+
+fn create_parent_dirs(path: &Path, options: &FileOptions) -> io::Result<()> {
+  if options.create_dirs {
+    if let Some(parent) = path.parent() {
+      if !parent.as_os_str().is_empty() {
+        fs::create_dir_all(parent)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(open_options: &mut OpenOptions, mode: Option<u32>) {
+  use std::os::unix::fs::OpenOptionsExt;
+
+  if let Some(mode) = mode {
+    open_options.mode(mode);
+  }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &mut OpenOptions, _: Option<u32>) {}
+
+fn numbered_path(path: &Path, idx: usize) -> PathBuf {
+  let mut numbered = path.as_os_str().to_owned();
+  numbered.push(format!(".{}", idx));
+  PathBuf::from(numbered)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::env;
+  use std::fs;
+  use std::path::{Path, PathBuf};
+
+  use logger::FileOptions;
+  use super::{LogFile, numbered_path};
+
+  #[cfg(feature = "time")]
+  use std::time::Duration;
+  #[cfg(feature = "time")]
+  use time;
+
+  #[cfg(feature = "time")]
+  use logger::RotationPeriod;
+  #[cfg(feature = "time")]
+  use super::Rotation;
+
+  /// An empty directory for one test to put its files in.
+  fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("artifact-test-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+  }
+
+  #[test]
+  fn rotates_by_size_and_keeps_the_newest_files() {
+    let dir = test_dir("rotate");
+    let path = dir.join("log");
+    let mut file = LogFile::open_rotating(path.clone(), 20, 2, &FileOptions::default()).unwrap();
+    for line in &["aaaaaaaaa", "bbbbbbbbb", "ccccccccc", "ddddddddd", "eeeeeeeee"] {
+      file.write_line(line).unwrap();
+    }
+    file.flush(false).unwrap();
+
+    assert_eq!(read(&path), "eeeeeeeee\n");
+    assert_eq!(read(&numbered_path(&path, 1)), "ccccccccc\nddddddddd\n");
+    assert_eq!(read(&numbered_path(&path, 2)), "aaaaaaaaa\nbbbbbbbbb\n");
+    assert!(!numbered_path(&path, 3).exists());
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn oversized_line_gets_a_file_of_its_own() {
+    let dir = test_dir("oversized");
+    let path = dir.join("log");
+    let mut file = LogFile::open_rotating(path.clone(), 5, 1, &FileOptions::default()).unwrap();
+    file.write_line("much longer than five bytes").unwrap();
+    file.write_line("next").unwrap();
+    file.flush(false).unwrap();
+
+    assert_eq!(read(&numbered_path(&path, 1)), "much longer than five bytes\n");
+    assert_eq!(read(&path), "next\n");
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn keeping_no_files_truncates() {
+    let dir = test_dir("truncate");
+    let path = dir.join("log");
+    let mut file = LogFile::open_rotating(path.clone(), 10, 0, &FileOptions::default()).unwrap();
+    file.write_line("first").unwrap();
+    file.write_line("second").unwrap();
+    file.flush(false).unwrap();
+
+    assert_eq!(read(&path), "second\n");
+    assert!(!numbered_path(&path, 1).exists());
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn rotating_files_get_the_directory_and_mode_options() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = test_dir("rotate-options");
+    let path = dir.join("nested").join("log");
+    let options = FileOptions{append: false, create_dirs: true, mode: Some(0o600)};
+    let mut file = LogFile::open_rotating(path.clone(), 5, 1, &options).unwrap();
+    file.write_line("first").unwrap();
+    file.write_line("second").unwrap();
+    file.flush(false).unwrap();
+
+    for path in &[path.clone(), numbered_path(&path, 1)] {
+      assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn keeps_writing_after_failed_rotation() {
+    let dir = test_dir("deleted");
+    let path = dir.join("log");
+    let mut file = LogFile::open_rotating(path.clone(), 15, 1, &FileOptions::default()).unwrap();
+    file.write_line("first line").unwrap();
+    fs::remove_file(&path).unwrap();
+
+    // Moving the deleted file fails, but the line still gets written to
+    // a new file at the path, as do the ones after.
+    assert!(file.write_line("second").is_err());
+    file.write_line("third").unwrap();
+    file.flush(false).unwrap();
+
+    assert_eq!(read(&path), "second\nthird\n");
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn failed_rotation_is_not_retried_on_every_line() {
+    let dir = test_dir("rename");
+    let path = dir.join("log");
+    // Nothing can be moved onto a directory which isn't empty.
+    fs::create_dir_all(numbered_path(&path, 1).join("taken")).unwrap();
+    let mut file = LogFile::open_rotating(path.clone(), 15, 1, &FileOptions::default()).unwrap();
+    file.write_line("first line").unwrap();
+
+    assert!(file.write_line("second").is_err());
+    file.write_line("third").unwrap();
+    assert!(file.write_line("fourth").is_err());
+    file.flush(false).unwrap();
+
+    assert_eq!(read(&path), "first line\nsecond\nthird\nfourth\n");
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[cfg(feature = "time")]
+  fn time_rotating(dir: &Path) -> LogFile {
+    let max_age = Duration::from_secs(2 * 86400);
+    LogFile::open_time_rotating(dir.join("%Y-%m-%d.log"), RotationPeriod::Daily, max_age, &FileOptions::default()).unwrap()
+  }
+
+  #[cfg(feature = "time")]
+  #[test]
+  fn prunes_old_files_matching_the_pattern() {
+    let dir = test_dir("prune");
+    for name in &["2000-01-01.log", "2000-01-01.log.bak", "notes.txt"] {
+      fs::write(dir.join(name), "old\n").unwrap();
+    }
+    let mut file = time_rotating(&dir);
+    file.write_line("new").unwrap();
+    file.flush(false).unwrap();
+
+    assert!(!dir.join("2000-01-01.log").exists());
+    assert!(dir.join("2000-01-01.log.bak").exists());
+    assert!(dir.join("notes.txt").exists());
+    let today = time::strftime("%Y-%m-%d.log", &time::now_utc()).unwrap();
+    assert_eq!(read(&dir.join(today)), "new\n");
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[cfg(feature = "time")]
+  #[test]
+  fn keeps_writing_when_the_next_period_cannot_be_opened() {
+    let dir = test_dir("switch");
+    let mut file = time_rotating(&dir);
+    let first_path = file.path().to_path_buf();
+    file.write_line("first").unwrap();
+
+    if let Rotation::ByTime(ref mut pattern, _, _, ref mut next_switch) = file.rotation {
+      *pattern = dir.join("missing").join("%Y-%m-%d.log");
+      *next_switch = 0;
+    }
+    assert!(file.write_line("second").is_err());
+    // Not tried again until the next period.
+    file.write_line("third").unwrap();
+    file.flush(false).unwrap();
+
+    assert_eq!(file.path(), first_path.as_path());
+    assert_eq!(read(&first_path), "first\nsecond\nthird\n");
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,218 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+//! Output to systemd-journald using its native protocol.  Each message
+//! is one datagram made up of `FIELD=value` entries.  Messages too big for
+//! a datagram are written to a sealed memfd, which is passed to journald
+//! instead.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::ptr;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+use libc;
+
+use level;
+use level::LogLevel;
+use field::Field;
+use location::Location;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+pub struct JournaldWriter{
+  socket: UnixDatagram,
+  path: PathBuf,
+  identifier: String,
+}
+
+impl JournaldWriter{
+  pub fn new(identifier: String) -> io::Result<JournaldWriter> {
+    let path = PathBuf::from(JOURNALD_SOCKET);
+    // Fail now, so the caller knows journald isn't available.
+    fs::metadata(&path)?;
+    Ok(JournaldWriter{socket: UnixDatagram::unbound()?, path, identifier})
+  }
+
+  pub fn write(&self, logger_name: &str, level: LogLevel, message: &str, fields: &[Field], location: Option<&Location>) {
+    let mut payload = Vec::new();
+    add_field(&mut payload, "MESSAGE", message);
+    add_field(&mut payload, "PRIORITY", &level::to_syslog_severity(level).to_string());
+    add_field(&mut payload, "SYSLOG_IDENTIFIER", &self.identifier);
+    add_field(&mut payload, "ARTIFACT_LOGGER", logger_name);
+    if let Some(location) = location {
+      add_field(&mut payload, "CODE_FILE", location.file);
+      add_field(&mut payload, "CODE_LINE", &location.line.to_string());
+      add_field(&mut payload, "ARTIFACT_MODULE", location.module_path);
+    }
+    for field in fields {
+      if let Some(name) = journal_field_name(&field.key) {
+        add_field(&mut payload, &name, &field.value.to_string());
+      }
+    }
+
+    // Nowhere to report failures to, as with the other outputs.
+    let _ = self.send(&payload);
+  }
+
+  fn send(&self, payload: &[u8]) -> io::Result<()> {
+    match self.socket.send_to(payload, &self.path) {
+      Ok(_) => Ok(()),
+      Err(ref e) if is_too_large(e) => self.send_with_memfd(payload),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn send_with_memfd(&self, payload: &[u8]) -> io::Result<()> {
+    let fd = unsafe {
+      libc::memfd_create(
+        b"artifact-journal\0".as_ptr() as *const libc::c_char,
+        libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+    };
+    if fd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    let mut memfd = unsafe { File::from_raw_fd(fd) };
+    memfd.write_all(payload)?;
+
+    // journald only accepts memfds which can no longer be changed.
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } < 0 {
+      return Err(io::Error::last_os_error());
+    }
+
+    send_fd(&self.socket, &self.path, memfd.as_raw_fd())
+  }
+}
+
+fn is_too_large(e: &io::Error) -> bool {
+  match e.raw_os_error() {
+    Some(code) => code == libc::EMSGSIZE || code == libc::ENOBUFS,
+    None => false
+  }
+}
+
+/// Journal field names may only contain uppercase letters, digits and
+/// underscores, and can't start with a digit or an underscore (those are
+/// reserved for fields journald adds itself).
+fn journal_field_name(key: &str) -> Option<String> {
+  let name: String =
+    key.chars()
+      .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+      .skip_while(|c| *c == '_' || c.is_ascii_digit())
+      .take(64)
+      .collect();
+  if name.is_empty() { None } else { Some(name) }
+}
+
+/// Appends a field.  Values containing newlines have to use the
+/// binary form: the name, a newline, the value's length as a
+/// little-endian u64, then the value itself.
+fn add_field(payload: &mut Vec<u8>, name: &str, value: &str) {
+  payload.extend_from_slice(name.as_bytes());
+  if value.contains('\n') {
+    payload.push(b'\n');
+    payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+  } else {
+    payload.push(b'=');
+  }
+  payload.extend_from_slice(value.as_bytes());
+  payload.push(b'\n');
+}
+
+/// Sends an empty datagram carrying the given file descriptor.
+fn send_fd(socket: &UnixDatagram, path: &Path, fd: RawFd) -> io::Result<()> {
+  unsafe {
+    let mut addr: libc::sockaddr_un = mem::zeroed();
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let path_bytes = path.as_os_str().as_bytes();
+    if path_bytes.len() >= addr.sun_path.len() {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "Journal socket path too long"));
+    }
+    for (dest, src) in addr.sun_path.iter_mut().zip(path_bytes.iter()) {
+      *dest = *src as libc::c_char;
+    }
+
+    let fd_size = mem::size_of::<libc::c_int>() as u32;
+    let mut control = vec![0u8; libc::CMSG_SPACE(fd_size) as usize];
+
+    let mut msg: libc::msghdr = mem::zeroed();
+    msg.msg_name = &mut addr as *mut libc::sockaddr_un as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_un>() as libc::socklen_t;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+
+    let cmsg = libc::CMSG_FIRSTHDR(&msg);
+    (*cmsg).cmsg_level = libc::SOL_SOCKET;
+    (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+    (*cmsg).cmsg_len = libc::CMSG_LEN(fd_size) as _;
+    ptr::write(libc::CMSG_DATA(cmsg) as *mut libc::c_int, fd);
+
+    if libc::sendmsg(socket.as_raw_fd(), &msg, 0) < 0 {
+      return Err(io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{add_field, journal_field_name};
+
+  #[test]
+  fn field_names_are_sanitised() {
+    assert_eq!(journal_field_name("user_id"), Some("USER_ID".to_string()));
+    assert_eq!(journal_field_name("http.status-code"), Some("HTTP_STATUS_CODE".to_string()));
+    assert_eq!(journal_field_name("_private"), Some("PRIVATE".to_string()));
+    assert_eq!(journal_field_name("2fa"), Some("FA".to_string()));
+    assert_eq!(journal_field_name("über"), Some("BER".to_string()));
+    assert_eq!(journal_field_name(&"k".repeat(100)), Some("K".repeat(64)));
+    assert_eq!(journal_field_name("__"), None);
+    assert_eq!(journal_field_name("42"), None);
+    assert_eq!(journal_field_name(""), None);
+  }
+
+  #[test]
+  fn single_line_values_use_the_text_form() {
+    let mut payload = Vec::new();
+    add_field(&mut payload, "MESSAGE", "hello = world");
+    add_field(&mut payload, "PRIORITY", "");
+    assert_eq!(payload, b"MESSAGE=hello = world\nPRIORITY=\n".to_vec());
+  }
+
+  #[test]
+  fn values_with_newlines_use_the_binary_form() {
+    let mut payload = Vec::new();
+    add_field(&mut payload, "MESSAGE", "one\ntwo");
+    let mut expected = b"MESSAGE\n".to_vec();
+    expected.extend_from_slice(&[7, 0, 0, 0, 0, 0, 0, 0]);
+    expected.extend_from_slice(b"one\ntwo\n");
+    assert_eq!(payload, expected);
+  }
+}
@@ -0,0 +1,197 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use std::collections::hash_map::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use level::LogLevel;
+
+/// Which messages sent to a logger the logger task would write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelFilter{
+  /// Messages at this level or more severe.
+  UpTo(LogLevel),
+  /// None at all.
+  Off,
+  /// Only the task can tell, e.g. because it reports messages which
+  /// have nowhere to go.
+  Unknown,
+}
+
+impl LevelFilter{
+  pub fn accepts(self, level: LogLevel) -> Option<bool> {
+    match self {
+      LevelFilter::UpTo(max_level) => Some(level <= max_level),
+      LevelFilter::Off => Some(false),
+      LevelFilter::Unknown => None,
+    }
+  }
+}
+
+/// The filters of every logger at some point in time.
+pub struct LevelSnapshot{
+  generation: usize,
+  filters: HashMap<String, LevelFilter>,
+  /// Used for loggers which don't exist.
+  default_filter: LevelFilter,
+}
+
+impl LevelSnapshot{
+  pub fn new(filters: HashMap<String, LevelFilter>, default_filter: LevelFilter) -> LevelSnapshot {
+    LevelSnapshot{generation: 0, filters, default_filter}
+  }
+
+  pub fn accepts(&self, logger_name: &str, level: LogLevel) -> Option<bool> {
+    self.filters.get(logger_name).cloned().unwrap_or(self.default_filter).accepts(level)
+  }
+}
+
+/// Shares the logger task's level filters with logging threads, so
+/// messages which would be dropped never get sent.
+///
+/// Threads keep their own copy of the latest snapshot, and only take the
+/// lock to fetch a new one after the generation changes.  Whenever a
+/// message which can change the filters is sent, the cache counts as
+/// out of date until the task has handled it, so a thread never filters
+/// with levels older than the ones it has set itself.
+pub struct LevelCache{
+  generation: AtomicUsize,
+  pending_updates: AtomicUsize,
+  latest: Mutex<Arc<LevelSnapshot>>,
+}
+
+impl LevelCache{
+  pub fn new() -> LevelCache {
+    LevelCache{
+      generation: AtomicUsize::new(0),
+      pending_updates: AtomicUsize::new(0),
+      latest: Mutex::new(Arc::new(LevelSnapshot::new(HashMap::new(), LevelFilter::Unknown)))}
+  }
+
+  /// Called before sending a message which can change the filters.
+  pub fn begin_update(&self) {
+    self.pending_updates.fetch_add(1, Ordering::SeqCst);
+  }
+
+  /// Called by the task once it has handled such a message.
+  pub fn finish_update(&self) {
+    self.pending_updates.fetch_sub(1, Ordering::SeqCst);
+  }
+
+  pub fn publish(&self, mut snapshot: LevelSnapshot) {
+    if let Ok(mut latest) = self.latest.lock() {
+      snapshot.generation = latest.generation + 1;
+      *latest = Arc::new(snapshot);
+      self.generation.store(latest.generation, Ordering::SeqCst);
+    }
+  }
+
+  /// Whether messages to the logger at the given level would be written,
+  /// replacing `local` with the latest snapshot if it's out of date.
+  /// None if the cache can't tell.
+  pub fn accepts(&self, local: &mut Option<Arc<LevelSnapshot>>, logger_name: &str, level: LogLevel) -> Option<bool> {
+    if self.pending_updates.load(Ordering::SeqCst) != 0 {
+      return None;
+    }
+
+    let generation = self.generation.load(Ordering::SeqCst);
+    let is_current = match *local {
+      Some(ref snapshot) => snapshot.generation == generation,
+      None => false
+    };
+    if !is_current {
+      *local = self.latest.lock().ok().map(|latest| latest.clone());
+    }
+
+    match *local {
+      Some(ref snapshot) => snapshot.accepts(logger_name, level),
+      None => None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::hash_map::HashMap;
+  use std::sync::Arc;
+
+  use level;
+  use super::{LevelCache, LevelFilter, LevelSnapshot};
+
+  fn snapshot(filters: &[(&str, LevelFilter)], default_filter: LevelFilter) -> LevelSnapshot {
+    let filters: HashMap<String, LevelFilter> =
+      filters.iter().map(|&(name, filter)| (name.to_string(), filter)).collect();
+    LevelSnapshot::new(filters, default_filter)
+  }
+
+  #[test]
+  fn filters_accept_levels() {
+    assert_eq!(LevelFilter::UpTo(level::INFO).accepts(level::WARNING), Some(true));
+    assert_eq!(LevelFilter::UpTo(level::INFO).accepts(level::INFO), Some(true));
+    assert_eq!(LevelFilter::UpTo(level::INFO).accepts(level::DEBUG), Some(false));
+    assert_eq!(LevelFilter::Off.accepts(level::WTF), Some(false));
+    assert_eq!(LevelFilter::Unknown.accepts(level::WTF), None);
+  }
+
+  #[test]
+  fn missing_loggers_get_the_default_filter() {
+    let snapshot = snapshot(&[("Foo", LevelFilter::UpTo(level::INFO))], LevelFilter::Off);
+    assert_eq!(snapshot.accepts("Foo", level::INFO), Some(true));
+    assert_eq!(snapshot.accepts("Bar", level::WTF), Some(false));
+  }
+
+  #[test]
+  fn new_cache_cannot_tell() {
+    let cache = LevelCache::new();
+    assert_eq!(cache.accepts(&mut None, "Foo", level::WTF), None);
+  }
+
+  #[test]
+  fn local_snapshot_is_kept_until_a_new_one_is_published() {
+    let cache = LevelCache::new();
+    cache.publish(snapshot(&[("Foo", LevelFilter::UpTo(level::INFO))], LevelFilter::Unknown));
+
+    let mut local = None;
+    assert_eq!(cache.accepts(&mut local, "Foo", level::DEBUG), Some(false));
+    let first = local.clone().unwrap();
+    assert_eq!(cache.accepts(&mut local, "Foo", level::INFO), Some(true));
+    assert!(Arc::ptr_eq(&first, local.as_ref().unwrap()));
+
+    cache.publish(snapshot(&[("Foo", LevelFilter::UpTo(level::DEBUG))], LevelFilter::Unknown));
+    assert_eq!(cache.accepts(&mut local, "Foo", level::DEBUG), Some(true));
+    assert!(!Arc::ptr_eq(&first, local.as_ref().unwrap()));
+  }
+
+  #[test]
+  fn pending_updates_make_the_cache_unsure() {
+    let cache = LevelCache::new();
+    cache.publish(snapshot(&[("Foo", LevelFilter::UpTo(level::INFO))], LevelFilter::Unknown));
+    let mut local = None;
+
+    cache.begin_update();
+    assert_eq!(cache.accepts(&mut local, "Foo", level::INFO), None);
+    cache.finish_update();
+    assert_eq!(cache.accepts(&mut local, "Foo", level::INFO), Some(true));
+  }
+}
@@ -21,5 +21,23 @@
  *
  */
 
+// With the `disabled` feature nothing talks to the logger task, but it's
+// still compiled so that the feature can't break the build.
+#![cfg_attr(feature = "disabled", allow(dead_code))]
+
 pub mod task;
+#[cfg(not(feature = "disabled"))]
 pub mod comm;
+#[cfg(feature = "disabled")]
+pub mod disabled;
+#[cfg(feature = "disabled")]
+pub use self::disabled as comm;
+pub mod levels;
+pub mod queue;
+pub mod file;
+pub mod syslog;
+pub mod network;
+#[cfg(target_os = "linux")]
+pub mod journald;
+#[cfg(target_os = "linux")]
+pub mod signal;
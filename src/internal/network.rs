@@ -0,0 +1,278 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use std::collections::VecDeque;
+use std::cmp;
+use std::io;
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+
+use logger::{NetworkOptions, NetworkProtocol};
+
+const CONNECT_TIMEOUT_MS: u64 = 1000;
+const WRITE_TIMEOUT_MS: u64 = 5000;
+const INITIAL_BACKOFF_MS: u64 = 100;
+const MAX_BACKOFF_MS: u64 = 30000;
+
+/// Something the internal logger should be told about.
+pub enum NetworkEvent{
+  Disconnected(io::Error),
+  /// Connection is back, with the number of messages dropped meanwhile.
+  Reconnected(u64),
+}
+
+enum Connection{
+  Tcp(Option<TcpStream>),
+  Udp(UdpSocket),
+}
+
+/// Sends newline terminated messages to a TCP or UDP endpoint.  While a TCP
+/// connection is down, messages are kept in a bounded backlog and the
+/// connection is retried with exponential backoff.
+///
+/// A message which fails part way through being written is sent again in
+/// full once the connection is back, so the collector can see the start
+/// of it on the old connection as well as the whole of it on the new one.
+/// Picking up from where the write stopped would instead leave the new
+/// connection starting in the middle of a line.
+pub struct NetworkWriter{
+  address: SocketAddr,
+  connection: Connection,
+  backlog: VecDeque<String>,
+  backlog_limit: usize,
+  dropped: u64,
+  backoff: Duration,
+  next_attempt: Instant,
+}
+
+impl NetworkWriter{
+  pub fn new(options: NetworkOptions) -> io::Result<NetworkWriter> {
+    let connection = match options.protocol {
+      // Not being able to connect yet is fine, the collector may not be up.
+      NetworkProtocol::Tcp => Connection::Tcp(connect_tcp(&options.address).ok()),
+      NetworkProtocol::Udp => {
+        let local_addr = if options.address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        Connection::Udp(UdpSocket::bind(local_addr)?)
+      }
+    };
+
+    Ok(NetworkWriter{
+      address: options.address,
+      connection,
+      backlog: VecDeque::new(),
+      backlog_limit: options.backlog,
+      dropped: 0,
+      backoff: Duration::from_millis(INITIAL_BACKOFF_MS),
+      next_attempt: Instant::now()})
+  }
+
+  pub fn address(&self) -> SocketAddr {
+    self.address
+  }
+
+  pub fn write(&mut self, message: &str) -> Option<NetworkEvent> {
+    let line = format!("{}\n", message);
+
+    match self.connection {
+      Connection::Udp(ref socket) => {
+        // Datagrams are fire and forget, there's no connection to restore.
+        let _ = socket.send_to(line.as_bytes(), self.address);
+        None
+      }
+      Connection::Tcp(_) => self.write_tcp(line)
+    }
+  }
+
+  fn write_tcp(&mut self, line: String) -> Option<NetworkEvent> {
+    let mut reconnected = false;
+
+    if self.is_disconnected() && Instant::now() >= self.next_attempt {
+      match connect_tcp(&self.address) {
+        Ok(stream) => {
+          self.connection = Connection::Tcp(Some(stream));
+          self.backoff = Duration::from_millis(INITIAL_BACKOFF_MS);
+          reconnected = true;
+        }
+        Err(_) => self.schedule_reconnect()
+      }
+    }
+
+    if self.is_disconnected() {
+      self.push_backlog(line);
+      return None;
+    }
+
+    match self.send_backlog().and_then(|_| self.send_line(&line)) {
+      Ok(()) if reconnected => {
+        let dropped = self.dropped;
+        self.dropped = 0;
+        Some(NetworkEvent::Reconnected(dropped))
+      }
+      Ok(()) => None,
+      Err(e) => {
+        self.push_backlog(line);
+        self.connection = Connection::Tcp(None);
+        self.schedule_reconnect();
+        Some(NetworkEvent::Disconnected(e))
+      }
+    }
+  }
+
+  fn is_disconnected(&self) -> bool {
+    matches!(self.connection, Connection::Tcp(None))
+  }
+
+  fn schedule_reconnect(&mut self) {
+    self.next_attempt = Instant::now() + self.backoff;
+    self.backoff = cmp::min(self.backoff * 2, Duration::from_millis(MAX_BACKOFF_MS));
+  }
+
+  fn push_backlog(&mut self, line: String) {
+    if self.backlog_limit == 0 {
+      self.dropped += 1;
+      return;
+    }
+    if self.backlog.len() >= self.backlog_limit {
+      self.backlog.pop_front();
+      self.dropped += 1;
+    }
+    self.backlog.push_back(line);
+  }
+
+  /// Writes out the backlog, oldest first.  A message is only removed
+  /// once all of it has been written, so nothing is lost if the write
+  /// fails, though part of a message may be sent twice.
+  fn send_backlog(&mut self) -> io::Result<()> {
+    if let Connection::Tcp(Some(ref mut stream)) = self.connection {
+      while let Some(line) = self.backlog.front() {
+        stream.write_all(line.as_bytes())?;
+        self.backlog.pop_front();
+      }
+    }
+    Ok(())
+  }
+
+  fn send_line(&mut self, line: &str) -> io::Result<()> {
+    if let Connection::Tcp(Some(ref mut stream)) = self.connection {
+      stream.write_all(line.as_bytes())?;
+    }
+    Ok(())
+  }
+}
+
+fn connect_tcp(address: &SocketAddr) -> io::Result<TcpStream> {
+  let stream = TcpStream::connect_timeout(address, Duration::from_millis(CONNECT_TIMEOUT_MS))?;
+  // A collector which stops reading shouldn't stall every other logger.
+  stream.set_write_timeout(Some(Duration::from_millis(WRITE_TIMEOUT_MS)))?;
+  Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{BufRead, BufReader};
+  use std::net::{SocketAddr, TcpListener, TcpStream};
+  use std::thread;
+  use std::time::{Duration, Instant};
+
+  use logger::{NetworkOptions, NetworkProtocol};
+  use super::{NetworkEvent, NetworkWriter, INITIAL_BACKOFF_MS, MAX_BACKOFF_MS};
+
+  /// An address nothing is listening on.
+  fn unused_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+  }
+
+  fn tcp_writer(address: SocketAddr, backlog: usize) -> NetworkWriter {
+    let mut options = NetworkOptions::new(NetworkProtocol::Tcp, address);
+    options.backlog = backlog;
+    NetworkWriter::new(options).unwrap()
+  }
+
+  fn read_lines(stream: TcpStream, count: usize) -> Vec<String> {
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    BufReader::new(stream).lines().take(count).map(|line| line.unwrap()).collect()
+  }
+
+  #[test]
+  fn backs_off_between_connection_attempts() {
+    let mut writer = tcp_writer(unused_address(), 10);
+    let mut expected = INITIAL_BACKOFF_MS;
+    for _ in 0..12 {
+      writer.next_attempt = Instant::now();
+      assert!(writer.write("line").is_none());
+      expected = (expected * 2).min(MAX_BACKOFF_MS);
+      assert_eq!(writer.backoff, Duration::from_millis(expected));
+    }
+
+    // No attempt is made before the backoff is up.
+    writer.write("line");
+    assert_eq!(writer.backoff, Duration::from_millis(MAX_BACKOFF_MS));
+  }
+
+  #[test]
+  fn keeps_a_bounded_backlog_until_it_reconnects() {
+    let address = unused_address();
+    let mut writer = tcp_writer(address, 2);
+    for line in &["one", "two", "three"] {
+      assert!(writer.write(line).is_none());
+    }
+
+    let listener = TcpListener::bind(address).unwrap();
+    writer.next_attempt = Instant::now();
+    match writer.write("four") {
+      Some(NetworkEvent::Reconnected(1)) => {}
+      _ => panic!("expected a reconnection with one message dropped")
+    }
+    let (stream, _) = listener.accept().unwrap();
+    assert_eq!(read_lines(stream, 3), vec!["two", "three", "four"]);
+  }
+
+  #[test]
+  fn resends_the_failed_message_after_the_connection_drops() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut writer = tcp_writer(listener.local_addr().unwrap(), 10);
+    drop(listener.accept().unwrap());
+
+    // Writes into a closed connection only fail once the peer's reset
+    // has arrived.
+    let mut failed = None;
+    for idx in 0..100 {
+      let line = format!("line {}", idx);
+      if let Some(NetworkEvent::Disconnected(_)) = writer.write(&line) {
+        failed = Some(line);
+        break;
+      }
+      thread::sleep(Duration::from_millis(10));
+    }
+    let failed = failed.expect("the dropped connection was never noticed");
+
+    writer.next_attempt = Instant::now();
+    match writer.write("after") {
+      Some(NetworkEvent::Reconnected(0)) => {}
+      _ => panic!("expected a reconnection with nothing dropped")
+    }
+    let (stream, _) = listener.accept().unwrap();
+    assert_eq!(read_lines(stream, 2), vec![failed, "after".to_string()]);
+  }
+}
@@ -0,0 +1,355 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::thread::ThreadId;
+
+use internal::task::LoggerMessage;
+use logger::OverflowPolicy;
+
+/// Carries messages to the logger thread.  Only log messages count
+/// towards the capacity and can be dropped; messages which configure
+/// loggers always get through.
+pub struct MessageQueue{
+  state: Mutex<QueueState>,
+  not_empty: Condvar,
+  not_full: Condvar,
+  capacity: Option<usize>,
+  policy: OverflowPolicy,
+}
+
+struct QueueState{
+  messages: VecDeque<LoggerMessage>,
+  /// Log messages currently in `messages`.
+  log_messages: usize,
+  /// Log messages dropped since that was last reported.
+  dropped: u64,
+  /// Log messages dropped since the queue was made, including any
+  /// discarded when it was closed.
+  dropped_total: u64,
+  closed: bool,
+  /// The thread taking messages off the queue, if it's been set.
+  consumer: Option<ThreadId>,
+}
+
+impl MessageQueue{
+  /// A queue which holds at most `capacity` log messages, or any number
+  /// if it's None.  A capacity of 0 is taken as 1, as nothing could
+  /// ever be queued otherwise.
+  pub fn new(capacity: Option<usize>, policy: OverflowPolicy) -> MessageQueue {
+    MessageQueue{
+      state: Mutex::new(
+        QueueState{
+          messages: VecDeque::new(),
+          log_messages: 0,
+          dropped: 0,
+          dropped_total: 0,
+          closed: false,
+          consumer: None}),
+      not_empty: Condvar::new(),
+      not_full: Condvar::new(),
+      capacity: capacity.map(|capacity| cmp::max(capacity, 1)),
+      policy}
+  }
+
+  /// Marks the current thread as the one taking messages off the queue.
+  /// Log messages it pushes itself, e.g. from a sink which logs, are
+  /// dropped rather than waited on if the queue is full, as nothing
+  /// would ever make room.
+  pub fn set_consumer(&self) {
+    self.lock().consumer = Some(thread::current().id());
+  }
+
+  /// Queues a message, following the overflow policy if the queue is
+  /// full.  Returns false once the queue is closed.
+  pub fn push(&self, message: LoggerMessage) -> bool {
+    let mut state = self.lock();
+    if state.closed {
+      return false;
+    }
+
+    let level = match message {
+      LoggerMessage::LogMessage(ref entry) => Some(entry.level),
+      _ => None
+    };
+    if let Some(level) = level {
+      let block = match self.policy {
+        OverflowPolicy::Block => true,
+        OverflowPolicy::DropBelow(threshold) => level <= threshold,
+        OverflowPolicy::DropNewest | OverflowPolicy::DropOldest => false,
+      } && state.consumer != Some(thread::current().id());
+
+      if block {
+        while self.is_full(&state) && !state.closed {
+          state = self.not_full.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        if state.closed {
+          return false;
+        }
+      } else if self.is_full(&state) {
+        state.dropped += 1;
+        state.dropped_total += 1;
+        if self.policy != OverflowPolicy::DropOldest || !state.remove_oldest_log_message() {
+          return true;
+        }
+      }
+      state.log_messages += 1;
+    }
+
+    state.messages.push_back(message);
+    self.not_empty.notify_one();
+    true
+  }
+
+  /// Waits for the next message, or returns None once the queue is
+  /// closed.  Also returns how many messages have been dropped since
+  /// that was last reported, but only once the queue has drained to
+  /// half its capacity, so that the report doesn't add to the pressure.
+  pub fn pop(&self) -> Option<(LoggerMessage, u64)> {
+    let mut state = self.lock();
+    loop {
+      if let Some(message) = state.pop_front() {
+        self.not_full.notify_one();
+        let dropped = match self.capacity {
+          Some(capacity) if state.log_messages <= capacity / 2 => state.dropped,
+          _ => 0
+        };
+        state.dropped -= dropped;
+        return Some((message, dropped));
+      }
+      if state.closed {
+        return None;
+      }
+      state = self.not_empty.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+  }
+
+  /// Takes the next message, if there is one, without waiting.
+  pub fn try_pop(&self) -> Option<LoggerMessage> {
+    let message = self.lock().pop_front();
+    self.not_full.notify_one();
+    message
+  }
+
+  /// Called when the logger thread stops, or is given up on.  Queued
+  /// messages are discarded, and later pushes fail, as do pushes
+  /// waiting for room.
+  pub fn close(&self) {
+    let mut state = self.lock();
+    state.closed = true;
+    state.dropped_total += state.log_messages as u64;
+    state.log_messages = 0;
+    let discarded = mem::take(&mut state.messages);
+    drop(state);
+    self.not_full.notify_all();
+    self.not_empty.notify_all();
+    // Dropped unlocked, as that may drop sinks which were on their
+    // way to the logger task.
+    drop(discarded);
+  }
+
+  /// How many log messages have been dropped, in all.
+  pub fn dropped_total(&self) -> u64 {
+    self.lock().dropped_total
+  }
+
+  fn is_full(&self, state: &QueueState) -> bool {
+    match self.capacity {
+      Some(capacity) => state.log_messages >= capacity,
+      None => false
+    }
+  }
+
+  fn lock(&self) -> MutexGuard<'_, QueueState> {
+    self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
+}
+
+impl QueueState{
+  fn pop_front(&mut self) -> Option<LoggerMessage> {
+    let message = self.messages.pop_front();
+    if let Some(LoggerMessage::LogMessage(_)) = message {
+      self.log_messages -= 1;
+    }
+    message
+  }
+
+  fn remove_oldest_log_message(&mut self) -> bool {
+    let oldest = self.messages.iter().position(|message| matches!(*message, LoggerMessage::LogMessage(_)));
+    match oldest {
+      Some(idx) => {
+        self.messages.remove(idx);
+        self.log_messages -= 1;
+        true
+      }
+      None => false
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+  use std::sync::mpsc::channel;
+  use std::thread;
+  use std::time::Duration;
+
+  use internal::task::{LoggerMessage, LogEntry};
+  use level;
+  use level::LogLevel;
+  use logger::OverflowPolicy;
+  use super::MessageQueue;
+
+  fn log(level: LogLevel, message: &str) -> LoggerMessage {
+    LoggerMessage::LogMessage(LogEntry::new("Foo", level, message.to_string(), Vec::new()))
+  }
+
+  /// Takes whatever is queued, as `(level, message)` for log messages
+  /// and `None` for anything else.
+  fn take_all(queue: &MessageQueue) -> Vec<Option<(LogLevel, String)>> {
+    let mut taken = Vec::new();
+    while let Some(message) = queue.try_pop() {
+      taken.push(match message {
+        LoggerMessage::LogMessage(entry) => Some((entry.level, entry.message)),
+        _ => None
+      });
+    }
+    taken
+  }
+
+  fn logged(level: LogLevel, message: &str) -> Option<(LogLevel, String)> {
+    Some((level, message.to_string()))
+  }
+
+  /// Pushes from another thread, and returns a receiver which gets the
+  /// result once the push returns.
+  fn push_in_background(queue: &Arc<MessageQueue>, message: LoggerMessage) -> ::std::sync::mpsc::Receiver<bool> {
+    let (send, recv) = channel();
+    let queue = queue.clone();
+    thread::spawn(move || {
+      let _ = send.send(queue.push(message));
+    });
+    recv
+  }
+
+  #[test]
+  fn drop_newest_keeps_what_is_queued() {
+    let queue = MessageQueue::new(Some(2), OverflowPolicy::DropNewest);
+    for message in &["a", "b", "c"] {
+      assert!(queue.push(log(level::INFO, message)));
+    }
+    assert_eq!(take_all(&queue), vec![logged(level::INFO, "a"), logged(level::INFO, "b")]);
+    assert_eq!(queue.dropped_total(), 1);
+  }
+
+  #[test]
+  fn drop_oldest_keeps_the_latest() {
+    let queue = MessageQueue::new(Some(2), OverflowPolicy::DropOldest);
+    for message in &["a", "b", "c"] {
+      assert!(queue.push(log(level::INFO, message)));
+    }
+    assert_eq!(take_all(&queue), vec![logged(level::INFO, "b"), logged(level::INFO, "c")]);
+    assert_eq!(queue.dropped_total(), 1);
+  }
+
+  #[test]
+  fn other_messages_do_not_count_and_are_never_dropped() {
+    let queue = MessageQueue::new(Some(1), OverflowPolicy::DropOldest);
+    assert!(queue.push(log(level::INFO, "a")));
+    assert!(queue.push(LoggerMessage::RegisterLevelString(level::INFO, "INFO".to_string())));
+    assert!(queue.push(log(level::INFO, "b")));
+    assert_eq!(take_all(&queue), vec![None, logged(level::INFO, "b")]);
+  }
+
+  #[test]
+  fn drop_below_drops_only_less_severe_messages() {
+    let queue = Arc::new(MessageQueue::new(Some(1), OverflowPolicy::DropBelow(level::WARNING)));
+    assert!(queue.push(log(level::INFO, "a")));
+    assert!(queue.push(log(level::INFO, "dropped")));
+    assert_eq!(queue.dropped_total(), 1);
+
+    let pushed = push_in_background(&queue, log(level::SEVERE, "waits"));
+    assert!(pushed.recv_timeout(Duration::from_millis(100)).is_err());
+    assert_eq!(take_all(&queue).len(), 1);
+    assert_eq!(pushed.recv_timeout(Duration::from_secs(5)), Ok(true));
+    assert_eq!(take_all(&queue), vec![logged(level::SEVERE, "waits")]);
+  }
+
+  #[test]
+  fn block_waits_for_room() {
+    let queue = Arc::new(MessageQueue::new(Some(1), OverflowPolicy::Block));
+    assert!(queue.push(log(level::INFO, "a")));
+
+    let pushed = push_in_background(&queue, log(level::INFO, "b"));
+    assert!(pushed.recv_timeout(Duration::from_millis(100)).is_err());
+    assert!(queue.pop().is_some());
+    assert_eq!(pushed.recv_timeout(Duration::from_secs(5)), Ok(true));
+    assert_eq!(queue.dropped_total(), 0);
+  }
+
+  #[test]
+  fn zero_capacity_holds_one_message() {
+    let queue = MessageQueue::new(Some(0), OverflowPolicy::Block);
+    assert!(queue.push(log(level::INFO, "a")));
+    assert_eq!(take_all(&queue), vec![logged(level::INFO, "a")]);
+  }
+
+  #[test]
+  fn consumer_never_waits_for_itself() {
+    let queue = MessageQueue::new(Some(1), OverflowPolicy::Block);
+    queue.set_consumer();
+    assert!(queue.push(log(level::INFO, "a")));
+    assert!(queue.push(log(level::INFO, "dropped")));
+    assert_eq!(take_all(&queue), vec![logged(level::INFO, "a")]);
+    assert_eq!(queue.dropped_total(), 1);
+  }
+
+  #[test]
+  fn closing_releases_waiting_pushes() {
+    let queue = Arc::new(MessageQueue::new(Some(1), OverflowPolicy::Block));
+    assert!(queue.push(log(level::INFO, "a")));
+    let pushed = push_in_background(&queue, log(level::INFO, "b"));
+    assert!(pushed.recv_timeout(Duration::from_millis(100)).is_err());
+
+    queue.close();
+    assert_eq!(pushed.recv_timeout(Duration::from_secs(5)), Ok(false));
+    assert!(!queue.push(log(level::INFO, "c")));
+    assert!(queue.pop().is_none());
+    // The one discarded on closing counts, the refused ones don't.
+    assert_eq!(queue.dropped_total(), 1);
+  }
+
+  #[test]
+  fn drops_are_reported_once_there_is_room() {
+    let queue = MessageQueue::new(Some(4), OverflowPolicy::DropNewest);
+    for message in &["a", "b", "c", "d", "e", "f"] {
+      queue.push(log(level::INFO, message));
+    }
+    let reported: Vec<u64> = (0..4).map(|_| queue.pop().unwrap().1).collect();
+    assert_eq!(reported, vec![0, 2, 0, 0]);
+  }
+}
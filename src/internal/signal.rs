@@ -0,0 +1,98 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+//! SIGHUP handling.  Very little is safe to do inside a signal handler,
+//! so the handler just writes a byte to a pipe.  A separate thread reads
+//! the pipe and passes the request on to the logger task.
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::thread;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use libc;
+
+use internal::comm::send_logger_message;
+use internal::task::LoggerMessage;
+
+static SIGHUP_PIPE_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+static INSTALL_LOCK: Mutex<bool> = Mutex::new(false);
+
+extern "C" fn handle_sighup(_: libc::c_int) {
+  let fd = SIGHUP_PIPE_WRITE_FD.load(Ordering::Relaxed);
+  if fd >= 0 {
+    // If the pipe is full a reopen is already pending, so a failed write is fine.
+    unsafe { libc::write(fd, b"\x01".as_ptr() as *const libc::c_void, 1); }
+  }
+}
+
+pub fn install_sighup_handler() -> io::Result<()> {
+  let mut installed = INSTALL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+  if *installed {
+    return Ok(());
+  }
+
+  let mut fds = [0 as libc::c_int; 2];
+  if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+    return Err(io::Error::last_os_error());
+  }
+  let (read_fd, write_fd) = (fds[0], fds[1]);
+  unsafe { libc::fcntl(write_fd, libc::F_SETFL, libc::O_NONBLOCK); }
+
+  thread::spawn(move || forward_sighups(read_fd));
+  SIGHUP_PIPE_WRITE_FD.store(write_fd, Ordering::Relaxed);
+
+  unsafe {
+    let mut action: libc::sigaction = mem::zeroed();
+    action.sa_sigaction = handle_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    libc::sigemptyset(&mut action.sa_mask);
+    if libc::sigaction(libc::SIGHUP, &action, ptr::null_mut()) != 0 {
+      let err = io::Error::last_os_error();
+      // Closing the write end lets the forwarding thread see EOF and exit.
+      SIGHUP_PIPE_WRITE_FD.store(-1, Ordering::Relaxed);
+      libc::close(write_fd);
+      return Err(err);
+    }
+  }
+
+  *installed = true;
+  Ok(())
+}
+
+fn forward_sighups(read_fd: libc::c_int) {
+  let mut buf = [0u8; 64];
+  loop {
+    let read = unsafe { libc::read(read_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    if read > 0 {
+      send_logger_message(LoggerMessage::ReopenFiles);
+    } else if read < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+      continue;
+    } else {
+      break;
+    }
+  }
+}
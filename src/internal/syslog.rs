@@ -0,0 +1,299 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use std::io;
+#[cfg(unix)]
+use std::fs;
+use std::net::{SocketAddr, UdpSocket};
+use std::process;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+#[cfg(feature = "time")]
+use time;
+
+use field;
+use field::Field;
+use level;
+use level::LogLevel;
+use logger::{SyslogOptions, SyslogTransport, SyslogFormat};
+
+/// SD-ID of the structured data element holding a message's fields.
+/// Artifact has no enterprise number of its own, so this uses the one
+/// set aside for documentation (RFC 5612).
+const FIELDS_SD_ID: &str = "fields@32473";
+
+enum SyslogSocket{
+  #[cfg(unix)]
+  Unix(UnixDatagram, PathBuf),
+  Udp(UdpSocket, SocketAddr),
+}
+
+/// Sends messages to a syslog daemon.
+pub struct SyslogWriter{
+  socket: SyslogSocket,
+  format: SyslogFormat,
+  facility: u8,
+  app_name: String,
+  hostname: String,
+  pid: u32,
+}
+
+impl SyslogWriter{
+  pub fn new(options: SyslogOptions) -> io::Result<SyslogWriter> {
+    let socket = match options.transport {
+      #[cfg(unix)]
+      SyslogTransport::Unix(path) => {
+        // Fail now rather than silently dropping everything later.
+        fs::metadata(&path)?;
+        SyslogSocket::Unix(UnixDatagram::unbound()?, path)
+      }
+      SyslogTransport::Udp(addr) => {
+        let local_addr = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        SyslogSocket::Udp(UdpSocket::bind(local_addr)?, addr)
+      }
+    };
+
+    Ok(SyslogWriter{
+      socket,
+      format: options.format,
+      facility: options.facility,
+      app_name: options.app_name,
+      hostname: hostname().unwrap_or_else(|| "-".to_string()),
+      pid: process::id()})
+  }
+
+  /// Sends a message.  With RFC 5424 the fields go in a structured
+  /// data element, RFC 3164 has no such thing so they're appended to
+  /// the message instead.
+  pub fn write(&self, level: LogLevel, message: &str, fields: &[Field]) {
+    let packet = match self.format {
+      SyslogFormat::Rfc5424 => self.format_rfc5424(level, message, fields),
+      SyslogFormat::Rfc3164 => self.format_rfc3164(level, &field::append_fields(message, fields)),
+    };
+
+    // As with the other outputs, there's nowhere to report a failure to.
+    let _ = match self.socket {
+      #[cfg(unix)]
+      SyslogSocket::Unix(ref socket, ref path) => socket.send_to(packet.as_bytes(), path),
+      SyslogSocket::Udp(ref socket, addr) => socket.send_to(packet.as_bytes(), addr),
+    };
+  }
+
+  fn priority(&self, level: LogLevel) -> u32 {
+    self.facility as u32 * 8 + level::to_syslog_severity(level) as u32
+  }
+
+  fn format_rfc5424(&self, level: LogLevel, message: &str, fields: &[Field]) -> String {
+    format!("<{}>1 {} {} {} {} - {} {}",
+            self.priority(level),
+            rfc5424_timestamp(),
+            self.hostname,
+            header_field(&self.app_name, 48),
+            self.pid,
+            structured_data(fields),
+            message)
+  }
+
+  fn format_rfc3164(&self, level: LogLevel, message: &str) -> String {
+    // Local daemons fill in the hostname themselves, remote ones need it.
+    let hostname = match self.socket {
+      SyslogSocket::Udp(..) => format!("{} ", self.hostname),
+      #[cfg(unix)]
+      SyslogSocket::Unix(..) => String::new(),
+    };
+    format!("<{}>{}{}{}[{}]: {}",
+            self.priority(level),
+            rfc3164_timestamp(),
+            hostname,
+            header_field(&self.app_name, 32),
+            self.pid,
+            message)
+  }
+}
+
+/// Header fields may only contain printable ASCII without spaces,
+/// and have a maximum length.
+fn header_field(value: &str, max_len: usize) -> String {
+  let cleaned: String =
+    value.chars()
+      .filter(|c| c.is_ascii_graphic())
+      .take(max_len)
+      .collect();
+  if cleaned.is_empty() { "-".to_string() } else { cleaned }
+}
+
+/// Formats fields as a single structured data element, or the nil
+/// value if there are none.  Parameter names which have nothing
+/// usable left after cleaning are skipped.
+fn structured_data(fields: &[Field]) -> String {
+  let mut element = String::new();
+  for field in fields {
+    let name: String =
+      field.key.chars()
+        .filter(|&c| c.is_ascii_graphic() && c != '=' && c != ']' && c != '"')
+        .take(32)
+        .collect();
+    if name.is_empty() {
+      continue;
+    }
+    element.push_str(&format!(" {}=\"", name));
+    for c in field.value.to_string().chars() {
+      if c == '"' || c == '\\' || c == ']' {
+        element.push('\\');
+      }
+      element.push(c);
+    }
+    element.push('"');
+  }
+  if element.is_empty() {
+    "-".to_string()
+  } else {
+    format!("[{}{}]", FIELDS_SD_ID, element)
+  }
+}
+
+#[cfg(feature = "time")]
+fn rfc5424_timestamp() -> String {
+  format!("{}", time::now_utc().rfc3339())
+}
+
+#[cfg(not(feature = "time"))]
+fn rfc5424_timestamp() -> String {
+  "-".to_string()
+}
+
+#[cfg(feature = "time")]
+fn rfc3164_timestamp() -> String {
+  time::strftime("%b %e %H:%M:%S ", &time::now()).unwrap_or_default()
+}
+
+/// Without a timestamp, the daemon uses the time it received the message.
+#[cfg(not(feature = "time"))]
+fn rfc3164_timestamp() -> String {
+  String::new()
+}
+
+#[cfg(target_os = "linux")]
+fn hostname() -> Option<String> {
+  use libc;
+
+  let mut buf = [0u8; 256];
+  if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+    return None;
+  }
+  let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+  let name = header_field(&String::from_utf8_lossy(&buf[..len]), 255);
+  if name == "-" { None } else { Some(name) }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn hostname() -> Option<String> {
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::UdpSocket;
+  use std::process;
+  use std::time::Duration;
+
+  use field::{Field, FieldValue};
+  use level;
+  use logger::{SyslogOptions, SyslogTransport, SyslogFormat};
+  use super::{SyslogWriter, header_field};
+
+  /// Sends one message through a UDP syslog writer and returns the
+  /// packet that arrived.
+  fn send_udp(format: SyslogFormat, facility: u8, app_name: &str, level: level::LogLevel, message: &str,
+              fields: &[Field]) -> String {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut options = SyslogOptions::new(SyslogTransport::Udp(server.local_addr().unwrap()), app_name);
+    options.format = format;
+    options.facility = facility;
+
+    SyslogWriter::new(options).unwrap().write(level, message, fields);
+
+    let mut buf = [0u8; 2048];
+    let len = server.recv(&mut buf).unwrap();
+    String::from_utf8(buf[..len].to_vec()).unwrap()
+  }
+
+  #[test]
+  fn rfc5424_packet() {
+    let packet = send_udp(SyslogFormat::Rfc5424, 1, "my app", level::SEVERE, "it broke", &[]);
+    let parts: Vec<&str> = packet.splitn(8, ' ').collect();
+
+    // user facility (1) * 8 + error severity (3)
+    assert_eq!(parts[0], "<11>1");
+    assert!(!parts[1].is_empty());
+    assert!(!parts[2].is_empty());
+    assert_eq!(parts[3], "myapp");
+    assert_eq!(parts[4], process::id().to_string());
+    assert_eq!(&parts[5..], &["-", "-", "it broke"]);
+  }
+
+  #[test]
+  fn rfc3164_packet() {
+    let packet = send_udp(SyslogFormat::Rfc3164, 23, "app", level::INFO, "hello there", &[]);
+
+    // local7 (23) * 8 + informational severity (6)
+    assert!(packet.starts_with("<190>"), "{}", packet);
+    assert!(packet.ends_with(&format!(" app[{}]: hello there", process::id())), "{}", packet);
+  }
+
+  #[test]
+  fn priority_follows_the_level() {
+    assert!(send_udp(SyslogFormat::Rfc5424, 16, "app", level::WTF, "m", &[]).starts_with("<129>1 "));
+    assert!(send_udp(SyslogFormat::Rfc5424, 16, "app", level::VERBOSE, "m", &[]).starts_with("<135>1 "));
+  }
+
+  #[test]
+  fn header_fields_are_cleaned_and_truncated() {
+    assert_eq!(header_field("a b\tc", 10), "abc");
+    assert_eq!(header_field("abcdef", 4), "abcd");
+    assert_eq!(header_field(" ", 4), "-");
+  }
+
+  #[test]
+  fn rfc5424_fields_are_structured_data() {
+    let fields = vec![
+      Field::new("status", FieldValue::Int(200)),
+      Field::new("path", FieldValue::Str("/a \"b\" [c]".to_string())),
+      Field::new("=", FieldValue::Bool(true))];
+    let packet = send_udp(SyslogFormat::Rfc5424, 1, "app", level::INFO, "done", &fields);
+
+    let expected = format!(r#" {} - [fields@32473 status="200" path="/a \"b\" [c\]"] done"#, process::id());
+    assert!(packet.ends_with(&expected), "{}", packet);
+  }
+
+  #[test]
+  fn rfc3164_fields_are_appended() {
+    let fields = vec![Field::new("status", FieldValue::Int(200))];
+    let packet = send_udp(SyslogFormat::Rfc3164, 1, "app", level::INFO, "done", &fields);
+    assert!(packet.ends_with(": done status=200"), "{}", packet);
+  }
+}
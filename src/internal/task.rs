@@ -22,26 +22,40 @@
  */
 
 use std::path::PathBuf;
-use std::sync::mpsc::Receiver;
 use std::thread::JoinHandle;
 use std::thread;
 use std::collections::hash_map::HashMap;
-use std::rc::Rc;
-use std::fs::File;
-use std::io::{Write, stderr};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::cmp;
+use std::mem;
+use std::io;
+use std::io::{Write, IsTerminal, stderr, stdout};
 use std::borrow::Borrow;
 
-use logger::LoggerOutput;
+use logger::{LoggerOutput, FileOptions, SyslogOptions, NetworkOptions, ShutdownReport};
 use level;
 use level::LogLevel;
 
 use format;
+use internal::file::LogFile;
+use internal::syslog::SyslogWriter;
+use internal::network::{NetworkWriter, NetworkEvent};
+#[cfg(target_os = "linux")]
+use internal::journald::JournaldWriter;
 use MessageFormatter;
+use format::{MessageInfo, Forwarding};
+use field::Field;
+use internal::levels::{LevelCache, LevelFilter, LevelSnapshot};
+use internal::queue::MessageQueue;
+use location::Location;
+use {LogSink, LogRecord, RecordCallback};
 
 use std::cell::RefCell;
 
-#[cfg(feature = "log")]
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 
 pub const INTERNAL_LOGGER_NAME:&'static str = "Artifact Internal";
 
@@ -52,38 +66,142 @@ pub enum DefaultLogTarget{
   LogToTargetNoIndicator(String),
 }
 
+/// A message logged through a `Logger`, on its way to the logger task.
+pub struct LogEntry{
+  pub logger: String,
+  pub level: LogLevel,
+  pub message: String,
+  pub fields: Vec<Field>,
+  /// Name of the thread which logged the message, or its id if unnamed.
+  pub thread: String,
+  pub location: Option<Location>,
+}
+
+impl LogEntry{
+  /// Creates an entry logged by the current thread.
+  pub fn new(logger: &str, level: LogLevel, message: String, fields: Vec<Field>) -> LogEntry {
+    let current = thread::current();
+    let thread = match current.name() {
+      Some(name) => name.to_string(),
+      None => format!("{:?}", current.id())
+    };
+    LogEntry{logger: logger.to_string(), level, message, fields, thread, location: None}
+  }
+
+  pub fn with_location(mut self, location: Location) -> LogEntry {
+    self.location = Some(location);
+    self
+  }
+}
+
 pub enum LoggerMessage{
-  PoisonPill,
-  LogMessage(String, LogLevel, String),
+  /// Stops the logger task, once whatever was queued behind it has
+  /// been handled or the deadline has passed.
+  Shutdown(Instant),
+  LogMessage(LogEntry),
   NewLogger(String, LogLevel, LoggerOutput),
   RedirectLogger(String, Option<LogLevel>, LoggerOutput),
   RegisterLevelString(LogLevel, String),
   Disable(String, bool),
   SetFormatter(String, Box<MessageFormatter>),
   SetDefaultFormatter(Box<MessageFormatter>),
-  #[cfg(feature = "log")]
   IsLogEnabled(String, LogLevel, Sender<bool>),
-  SetDefaultLogTarget(DefaultLogTarget)
+  SetDefaultLogTarget(DefaultLogTarget),
+  ReopenFiles,
+  Snapshot(String, Sender<Vec<String>>),
+  /// Flushes the outputs of the named logger, or of every logger if
+  /// no name is given, then replies.  Files are synced to disk as well
+  /// if the flag is set.
+  Flush(Option<String>, bool, Sender<()>),
 }
 
 enum LoggerInstance{
-  FileLoggerInst(Rc<RefCell<File>>, PathBuf),
+  FileLoggerInst(Arc<Mutex<LogFile>>, PathBuf),
   StdoutLoggerInst,
   StderrLoggerInst,
   MultiLoggerInst(Vec<String>),
+  SyslogLoggerInst(SyslogWriter),
+  #[cfg(target_os = "linux")]
+  JournaldLoggerInst(JournaldWriter),
+  NetworkLoggerInst(RefCell<NetworkWriter>),
+  MemoryRingLoggerInst(RefCell<VecDeque<String>>, usize),
+  CustomLoggerInst(Arc<Mutex<LogSink>>),
+  ChannelLoggerInst(Arc<Sender<LogRecord>>),
+  CallbackLoggerInst(Arc<Mutex<RecordCallback>>),
+}
+
+/// Tells custom sinks apart by the address they're stored at.
+fn sink_key<T: ?Sized>(sink: &Arc<T>) -> usize {
+  Arc::as_ptr(sink) as *const () as usize
+}
+
+/// A message on its way to being written.
+struct MessageEntry<'a>{
+  logger_name: &'a str,
+  level: LogLevel,
+  message: &'a str,
+  fields: &'a [Field],
+  /// None for messages from the logger task itself.
+  thread: Option<&'a str>,
+  location: Option<&'a Location>,
 }
 
 struct LoggerTaskInfo{
-  loggers: HashMap<String, (LogLevel, LoggerInstance)>,
+  /// Each logger's level and output, and the id `TaskStats` knows it by.
+  loggers: HashMap<String, (LogLevel, LoggerInstance, usize)>,
   level_strings: HashMap<LogLevel, String>,
   disabled: HashMap<String, bool>,
   formatters: HashMap<String, Box<MessageFormatter>>,
   default_formatter: Box<MessageFormatter>,
-  default_logger: DefaultLogTarget
+  default_logger: DefaultLogTarget,
+  stdout_is_terminal: bool,
+  stderr_is_terminal: bool,
+  level_cache: Arc<LevelCache>,
+  stats: Arc<TaskStats>,
+  /// How many loggers use each custom sink, keyed by `sink_key`, so
+  /// that a shared sink is only closed once the last of them is gone.
+  sink_uses: HashMap<usize, usize>,
+  /// The id given to the most recently added logger.
+  last_logger_id: usize,
 }
 
 impl LoggerInstance{
-  fn write(&self, self_name: &str, message:&str, level:LogLevel, task_info:&LoggerTaskInfo) {
+  fn write<'a>(&'a self,
+               self_name: &'a str,
+               self_id: usize,
+               entry: &MessageEntry<'a>,
+               route: &mut Vec<Forwarding<'a>>,
+               task_info: &'a LoggerTaskInfo) {
+    if let LoggerInstance::MultiLoggerInst(ref other_loggers) = *self {
+      for logger in other_loggers.iter() {
+        route.push(Forwarding::Multi{from: self_name, to: logger});
+        task_info.route_message(logger, entry, route);
+        route.pop();
+      }
+      return;
+    }
+
+    let to_terminal = match *self {
+      LoggerInstance::StdoutLoggerInst => task_info.stdout_is_terminal,
+      LoggerInstance::StderrLoggerInst => task_info.stderr_is_terminal,
+      _ => false
+    };
+    // These outputs send the fields separately, so there's no need for
+    // the formatter to add them to the text as well.
+    let sends_fields = match *self {
+      LoggerInstance::SyslogLoggerInst(_) => true,
+      #[cfg(target_os = "linux")]
+      LoggerInstance::JournaldLoggerInst(_) => true,
+      _ => false
+    };
+    let formatted = task_info.format_entry(entry, route, to_terminal, !sends_fields);
+    let message: &str = &formatted;
+    let level = entry.level;
+    let fields = entry.fields;
+
+    // Noted so that a sink which hangs can be named if shutting down
+    // times out.
+    task_info.stats.set_busy(self_id);
     match *self {
       LoggerInstance::StdoutLoggerInst => {
         println!("{}", message);
@@ -92,17 +210,108 @@ impl LoggerInstance{
         // discard failures.  What are we going to do, log it?
         let _ = writeln!(&mut stderr(), "{}", message);
       }
-      LoggerInstance::FileLoggerInst(ref file_writer, _) => {
-        let _ = writeln!(file_writer.borrow_mut(), "{}", message);
+      LoggerInstance::FileLoggerInst(ref file_writer, ref path) => {
+        let result = match file_writer.lock() {
+          Ok(mut file) => file.write_line(message),
+          Err(_) => Ok(())
+        };
+        // Not reported for the internal logger's own messages, which
+        // could fail the same way forever.
+        if let Err(e) = result {
+          if entry.logger_name != INTERNAL_LOGGER_NAME {
+            task_info.log_internal(
+              format!("Problem with the log file {} of the {} logger: {}", path.display(), self_name, e),
+              level::SEVERE);
+          }
+        }
+      }
+      // Handled above, as forwarding doesn't need a formatted message.
+      LoggerInstance::MultiLoggerInst(_) => {}
+      LoggerInstance::SyslogLoggerInst(ref writer) => {
+        writer.write(level, message, fields);
+      }
+      #[cfg(target_os = "linux")]
+      LoggerInstance::JournaldLoggerInst(ref writer) => {
+        writer.write(self_name, level, message, fields, entry.location);
+      }
+      LoggerInstance::NetworkLoggerInst(ref writer) => {
+        let event = writer.borrow_mut().write(message);
+        let address = writer.borrow().address();
+        match event {
+          Some(NetworkEvent::Disconnected(e)) =>
+            task_info.log_internal(
+              format!("The {} logger lost its connection to {}: {}. Messages will be buffered until it reconnects.",
+                      self_name, address, e),
+              level::WARNING),
+          Some(NetworkEvent::Reconnected(0)) =>
+            task_info.log_internal(
+              format!("The {} logger reconnected to {}.", self_name, address),
+              level::INFO),
+          Some(NetworkEvent::Reconnected(dropped)) =>
+            task_info.log_internal(
+              format!("The {} logger reconnected to {}. {} messages were dropped while it was disconnected.",
+                      self_name, address, dropped),
+              level::WARNING),
+          None => {}
+        }
+      }
+      LoggerInstance::MemoryRingLoggerInst(ref ring, capacity) => {
+        let mut ring = ring.borrow_mut();
+        if ring.len() >= capacity {
+          ring.pop_front();
+        }
+        if capacity > 0 {
+          ring.push_back(message.to_string());
+        }
+      }
+      LoggerInstance::CustomLoggerInst(ref sink) => {
+        if let Ok(mut sink) = sink.lock() {
+          sink.write_with_fields(self_name, level, message, fields);
+        }
+      }
+      LoggerInstance::ChannelLoggerInst(ref sender) => {
+        // The receiver going away just means nobody wants the messages.
+        let _ = sender.send(task_info.make_record(self_name, entry, message));
       }
-      LoggerInstance::MultiLoggerInst(ref other_loggers) => {
-        let formatter = task_info.get_formatter(self_name);
-        for logger in other_loggers.iter() {
-          let formatted_msg = formatter.add_logger_name_to_multi_message(logger, message);
-          task_info.write_formatted_message(logger, level, &formatted_msg);
+      LoggerInstance::CallbackLoggerInst(ref callback) => {
+        if let Ok(mut callback) = callback.lock() {
+          (*callback)(&task_info.make_record(self_name, entry, message));
         }
       }
     }
+    task_info.stats.set_busy(0);
+    if entry.thread.is_some() {
+      task_info.stats.written.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  /// Pushes out anything the output has buffered.  Multi-loggers are
+  /// handled by `LoggerTaskInfo::flush_logger`.  Returns false if the
+  /// output couldn't be flushed.
+  fn flush(&self, self_name: &str, sync: bool, task_info: &LoggerTaskInfo) -> bool {
+    match *self {
+      LoggerInstance::StdoutLoggerInst => stdout().flush().is_ok(),
+      LoggerInstance::StderrLoggerInst => stderr().flush().is_ok(),
+      LoggerInstance::FileLoggerInst(ref file, _) => {
+        let result = file.lock().map(|mut file| file.flush(sync));
+        if let Ok(Err(ref e)) = result {
+          task_info.log_internal(
+            format!("Could not flush the log file of the {} logger: {}", self_name, e),
+            level::SEVERE);
+        }
+        matches!(result, Ok(Ok(())))
+      }
+      LoggerInstance::CustomLoggerInst(ref sink) => {
+        match sink.lock() {
+          Ok(mut sink) => {
+            sink.flush();
+            true
+          }
+          Err(_) => false
+        }
+      }
+      _ => true
+    }
   }
 
   fn logger_type_name(&self) -> &'static str {
@@ -110,13 +319,21 @@ impl LoggerInstance{
       LoggerInstance::StdoutLoggerInst => "StdoutLogger",
       LoggerInstance::StderrLoggerInst => "StderrLogger",
       LoggerInstance::FileLoggerInst(_,_) => "FileLogger",
-      LoggerInstance::MultiLoggerInst(_) => "MultiLogger"
+      LoggerInstance::MultiLoggerInst(_) => "MultiLogger",
+      LoggerInstance::SyslogLoggerInst(_) => "SyslogLogger",
+      #[cfg(target_os = "linux")]
+      LoggerInstance::JournaldLoggerInst(_) => "JournaldLogger",
+      LoggerInstance::NetworkLoggerInst(_) => "NetworkLogger",
+      LoggerInstance::MemoryRingLoggerInst(_, _) => "MemoryRingLogger",
+      LoggerInstance::CustomLoggerInst(_) => "CustomLogger",
+      LoggerInstance::ChannelLoggerInst(_) => "ChannelLogger",
+      LoggerInstance::CallbackLoggerInst(_) => "CallbackLogger",
     }
   }
 }
 
 impl LoggerTaskInfo{
-  fn new() -> LoggerTaskInfo {
+  fn new(level_cache: Arc<LevelCache>, stats: Arc<TaskStats>) -> LoggerTaskInfo {
     let mut task =
       LoggerTaskInfo{
         loggers: HashMap::new(),
@@ -124,11 +341,18 @@ impl LoggerTaskInfo{
         disabled: HashMap::new(),
         formatters: HashMap::new(),
         default_formatter: format::new_basic_format_instance(),
-        default_logger: DefaultLogTarget::NoDefault};
+        default_logger: DefaultLogTarget::NoDefault,
+        stdout_is_terminal: stdout().is_terminal(),
+        stderr_is_terminal: stderr().is_terminal(),
+        level_cache,
+        stats,
+        sink_uses: HashMap::new(),
+        last_logger_id: 0};
     task.add_logger(
       INTERNAL_LOGGER_NAME.to_string(),
       level::DEFAULT,
       LoggerOutput::StdoutLog);
+    task.publish_levels();
     task
   }
 
@@ -136,30 +360,160 @@ impl LoggerTaskInfo{
     self.formatters.get(logger_name).unwrap_or(&self.default_formatter)
   }
 
-  fn write_message<MsgTy:Borrow<str>>(&self, logger_name: &str, msg_level: LogLevel, msg: MsgTy) {
-    let formatter = self.get_formatter(logger_name);
-    let message = formatter.format_message(logger_name, &self.level_string(msg_level), msg.borrow());
-    self.write_formatted_message(
-      logger_name,
-      msg_level,
-      &message);
+  fn write_message<MsgTy:Borrow<str>>(&self, logger_name: &str, msg_level: LogLevel, msg: MsgTy, fields: &[Field]) {
+    let entry =
+      MessageEntry{
+        logger_name,
+        level: msg_level,
+        message: msg.borrow(),
+        fields,
+        thread: None,
+        location: None};
+    self.route_message(logger_name, &entry, &mut Vec::new());
+  }
+
+  fn write_log_entry(&self, log_entry: &LogEntry) {
+    let entry =
+      MessageEntry{
+        logger_name: &log_entry.logger,
+        level: log_entry.level,
+        message: &log_entry.message,
+        fields: &log_entry.fields,
+        thread: Some(&log_entry.thread),
+        location: log_entry.location.as_ref()};
+    self.route_message(&log_entry.logger, &entry, &mut Vec::new());
   }
 
-  fn write_formatted_message(&self, logger_name: &str, msg_level: LogLevel, msg: &str) {
+  /// Sends a message to a logger, following multi-loggers and the default
+  /// logger until it reaches loggers which actually write it.  The route
+  /// records how it got there.
+  fn route_message<'a>(&'a self, logger_name: &'a str, entry: &MessageEntry<'a>, route: &mut Vec<Forwarding<'a>>) {
     if self.disabled.contains_key(logger_name) {
       return;
     }
     match self.loggers.get(logger_name) {
-      Some(&(logger_level, ref logger)) => {
-        if msg_level <= logger_level {
-          logger.write(logger_name, msg, msg_level, &self);
+      Some(&(logger_level, ref logger, id)) => {
+        if entry.level <= logger_level {
+          logger.write(logger_name, id, entry, route, self);
         }
       }
-      None => self.handle_nonexistant_logger(logger_name, msg_level, msg)
+      None => self.handle_nonexistant_logger(logger_name, entry, route)
     }
   }
 
-  fn handle_nonexistant_logger(&self, logger: &str, msg_level: LogLevel, msg: &str){
+  /// Whether a message at this level sent to this logger would be
+  /// written anywhere.
+  fn accepts(&self, logger_name: &str, level: LogLevel) -> bool {
+    self.level_filter(logger_name, &mut Vec::new()).accepts(level).unwrap_or(false)
+  }
+
+  /// Works out which messages sent to a logger end up being written,
+  /// following the same path as `route_message`.  `visiting` holds the
+  /// loggers already on the path, to stop at cycles.
+  fn level_filter<'a>(&'a self, logger_name: &'a str, visiting: &mut Vec<&'a str>) -> LevelFilter {
+    use self::DefaultLogTarget::*;
+    if self.disabled.contains_key(logger_name) {
+      return LevelFilter::Off;
+    }
+    if visiting.contains(&logger_name) {
+      return LevelFilter::Unknown;
+    }
+
+    visiting.push(logger_name);
+    let filter = match self.loggers.get(logger_name) {
+      Some(&(logger_level, LoggerInstance::MultiLoggerInst(ref other_loggers), _)) => {
+        let mut filter = LevelFilter::Off;
+        for logger in other_loggers {
+          filter = match (filter, self.level_filter(logger, visiting)) {
+            (LevelFilter::Unknown, _) | (_, LevelFilter::Unknown) => LevelFilter::Unknown,
+            (LevelFilter::Off, other) | (other, LevelFilter::Off) => other,
+            (LevelFilter::UpTo(a), LevelFilter::UpTo(b)) => LevelFilter::UpTo(cmp::max(a, b)),
+          };
+        }
+        match filter {
+          LevelFilter::UpTo(max_level) => LevelFilter::UpTo(cmp::min(max_level, logger_level)),
+          other => other
+        }
+      }
+      Some(&(logger_level, _, _)) => LevelFilter::UpTo(logger_level),
+      // Messages to missing loggers get reported, so they must reach the task.
+      None => match self.default_logger {
+        NoDefault => LevelFilter::Unknown,
+        LogToTarget(ref loggername) | LogToTargetNoIndicator(ref loggername) =>
+          if loggername == logger_name {
+            LevelFilter::Unknown
+          } else {
+            self.level_filter(loggername, visiting)
+          }
+      }
+    };
+    visiting.pop();
+    filter
+  }
+
+  /// Called after handling a message for which the sender marked the
+  /// level cache as out of date.
+  fn finish_level_update(&self) {
+    self.publish_levels();
+    self.level_cache.finish_update();
+  }
+
+  /// Flushes every output a message sent to the logger could reach.
+  fn flush_logger<'a>(&'a self, logger_name: &'a str, sync: bool, visiting: &mut Vec<&'a str>) {
+    if visiting.contains(&logger_name) {
+      return;
+    }
+    visiting.push(logger_name);
+    match self.loggers.get(logger_name) {
+      Some(&(_, LoggerInstance::MultiLoggerInst(ref other_loggers), _)) => {
+        for logger in other_loggers {
+          self.flush_logger(logger, sync, visiting);
+        }
+      }
+      Some((_, instance, _)) => {
+        instance.flush(logger_name, sync, self);
+      }
+      None => match self.default_logger {
+        DefaultLogTarget::NoDefault => {}
+        DefaultLogTarget::LogToTarget(ref loggername) | DefaultLogTarget::LogToTargetNoIndicator(ref loggername) =>
+          self.flush_logger(loggername, sync, visiting),
+      }
+    }
+  }
+
+  /// Flushes every logger, then closes them one by one, noting in the
+  /// task stats which fail and which is being closed.
+  fn shut_down(mut self) {
+    let stats = self.stats.clone();
+    for (name, &(_, ref instance, id)) in &self.loggers {
+      stats.set_busy(id);
+      if !instance.flush(name, true, &self) {
+        stats.add_failed_sink(name.clone());
+      }
+    }
+    let loggers: Vec<(LogLevel, LoggerInstance, usize)> = self.loggers.drain().map(|(_, logger)| logger).collect();
+    for (_, instance, id) in loggers {
+      stats.set_busy(id);
+      self.release(instance, id);
+    }
+    stats.set_busy(0);
+  }
+
+  /// Shares the current level filters with logging threads.
+  fn publish_levels(&self) {
+    let filters =
+      self.loggers.keys().chain(self.disabled.keys())
+        .map(|name| (name.clone(), self.level_filter(name, &mut Vec::new())))
+        .collect();
+    let default_filter = match self.default_logger {
+      DefaultLogTarget::NoDefault => LevelFilter::Unknown,
+      DefaultLogTarget::LogToTarget(ref loggername) | DefaultLogTarget::LogToTargetNoIndicator(ref loggername) =>
+        self.level_filter(loggername, &mut Vec::new()),
+    };
+    self.level_cache.publish(LevelSnapshot::new(filters, default_filter));
+  }
+
+  fn handle_nonexistant_logger<'a>(&'a self, logger: &'a str, entry: &MessageEntry<'a>, route: &mut Vec<Forwarding<'a>>){
     use self::DefaultLogTarget::*;
     match self.default_logger {
       NoDefault =>
@@ -177,16 +531,49 @@ impl LoggerTaskInfo{
           level::SEVERE)
       }
       LogToTarget(ref loggername) => {
-        let full_message = self.get_formatter(loggername)
-              .add_defaulting_name_to_message(loggername, msg);
-        self.write_formatted_message(loggername, msg_level, &full_message);
+        route.push(Forwarding::Default{from: logger, to: loggername});
+        self.route_message(loggername, entry, route);
+        route.pop();
       }
       LogToTargetNoIndicator(ref loggername) => {
-        self.write_formatted_message(loggername, msg_level, msg);
+        self.route_message(loggername, entry, route);
       }
     }
   }
 
+  /// Formats a message with the formatter of the logger it was sent to.
+  /// Unless that formatter shows the route itself, each forwarding
+  /// logger's formatter then gets to mark the message as forwarded, as
+  /// that's how forwarding used to be shown.
+  fn format_entry(&self, entry: &MessageEntry, route: &[Forwarding], to_terminal: bool, with_fields: bool) -> String {
+    let formatter = self.get_formatter(entry.logger_name);
+    let level_string = self.level_string(entry.level);
+    let mut message = formatter.format_message_info(
+      &MessageInfo{
+        logger_name: entry.logger_name,
+        level: entry.level,
+        level_string: &level_string,
+        message: entry.message,
+        fields: if with_fields { entry.fields } else { &[] },
+        thread: entry.thread,
+        forwarded: route,
+        to_terminal,
+        location: entry.location});
+
+    if formatter.shows_forwarding() {
+      return message;
+    }
+    for hop in route {
+      message = match *hop {
+        Forwarding::Multi{from, to} =>
+          self.get_formatter(from).add_logger_name_to_multi_message(to, &message),
+        Forwarding::Default{to, ..} =>
+          self.get_formatter(to).add_defaulting_name_to_message(to, &message),
+      };
+    }
+    message
+  }
+
   fn level_string(&self, level: LogLevel) -> String {
     match self.level_strings.get(&level) {
       Some(ref strval) => strval.to_string(),
@@ -194,8 +581,18 @@ impl LoggerTaskInfo{
     }
   }
 
+  fn make_record(&self, logger_name: &str, entry: &MessageEntry, message: &str) -> LogRecord {
+    LogRecord{
+      logger: logger_name.to_string(),
+      level: entry.level,
+      level_string: self.level_string(entry.level),
+      message: message.to_string(),
+      fields: entry.fields.to_vec(),
+      location: entry.location.cloned()}
+  }
+
   fn get_logger_for_path(&self, path:&PathBuf) -> Option<LoggerInstance> {
-    for &(_, ref known_logger) in self.loggers.values() {
+    for &(_, ref known_logger, _) in self.loggers.values() {
       match known_logger {
         &LoggerInstance::FileLoggerInst(ref cell, ref prev_path) => {
           if *prev_path == *path {
@@ -208,14 +605,26 @@ impl LoggerTaskInfo{
     None
   }
 
-  fn add_file_logger(&mut self, logger:String, level:LogLevel, path:PathBuf) {
+  fn add_file_logger<F>(&mut self, logger:String, level:LogLevel, path:PathBuf, rotating: bool, open: F)
+      where F: FnOnce(PathBuf) -> io::Result<LogFile> {
     let previous_file_logger:Option<LoggerInstance> = self.get_logger_for_path(&path);
 
+    if let Some(LoggerInstance::FileLoggerInst(ref file, _)) = previous_file_logger {
+      let shared_rotates = file.lock().map(|file| file.rotates()).unwrap_or(false);
+      if rotating || shared_rotates {
+        self.log_internal(
+          format!("The {} logger writes to {}, which another logger already has open.  \
+                   The file keeps the settings it was first opened with.",
+                  logger, path.display()),
+          level::WARNING);
+      }
+    }
+
     let file_logger_instance = previous_file_logger.or_else(
       ||
-        match File::create(&path) {
+        match open(path.clone()) {
           Ok(new_file) =>
-            Some(LoggerInstance::FileLoggerInst(Rc::new(RefCell::new(new_file)), path.clone())),
+            Some(LoggerInstance::FileLoggerInst(Arc::new(Mutex::new(new_file)), path.clone())),
           Err(_) => {
             None
           }
@@ -223,9 +632,7 @@ impl LoggerTaskInfo{
 
     match file_logger_instance {
       Some(instance) => {
-        self.loggers.insert(
-          logger,
-          (level, instance));
+        self.insert_logger(logger, level, instance);
       }
       None =>
         if let Some(path_str) = path.as_os_str().to_str() {
@@ -240,10 +647,78 @@ impl LoggerTaskInfo{
     }
   }
 
+  fn snapshot(&self, logger_name: &str) -> Vec<String> {
+    match self.loggers.get(logger_name) {
+      Some(&(_, LoggerInstance::MemoryRingLoggerInst(ref ring, _), _)) =>
+        ring.borrow().iter().cloned().collect(),
+      _ => Vec::new()
+    }
+  }
+
+  fn reopen_files(&self) {
+    let mut reopened: Vec<&PathBuf> = Vec::new();
+    let mut failures = Vec::new();
+    for (_, known_logger, _) in self.loggers.values() {
+      if let LoggerInstance::FileLoggerInst(ref file, ref path) = *known_logger {
+        if reopened.contains(&path) {
+          continue;
+        }
+        reopened.push(path);
+
+        if let Ok(mut file) = file.lock() {
+          if let Err(e) = file.reopen() {
+            failures.push(format!("Could not reopen log file {}: {}", file.path().to_string_lossy(), e));
+          }
+        }
+      }
+    }
+    // Logged once the files are unlocked, as the internal logger may
+    // write to one of them.
+    for failure in failures {
+      self.log_internal(failure, level::SEVERE);
+    }
+  }
+
   fn add_multi_logger(&mut self, logger:String, level:LogLevel, direct_to:Vec<String>){
     let instance = LoggerInstance::MultiLoggerInst(direct_to);
-    self.loggers.insert(logger,
-                        (level, instance));
+    self.insert_logger(logger, level, instance);
+  }
+
+  fn add_syslog_logger(&mut self, logger:String, level:LogLevel, options:SyslogOptions) {
+    match SyslogWriter::new(options) {
+      Ok(writer) => {
+        self.insert_logger(logger, level, LoggerInstance::SyslogLoggerInst(writer));
+      }
+      Err(e) =>
+        self.log_internal(
+          format!("Could not connect the {} logger to syslog: {}", logger, e),
+          level::SEVERE)
+    }
+  }
+
+  fn add_network_logger(&mut self, logger:String, level:LogLevel, options:NetworkOptions) {
+    match NetworkWriter::new(options) {
+      Ok(writer) => {
+        self.insert_logger(logger, level, LoggerInstance::NetworkLoggerInst(RefCell::new(writer)));
+      }
+      Err(e) =>
+        self.log_internal(
+          format!("Could not set up the {} network logger: {}", logger, e),
+          level::SEVERE)
+    }
+  }
+
+  #[cfg(target_os = "linux")]
+  fn add_journald_logger(&mut self, logger:String, level:LogLevel, identifier:String) {
+    match JournaldWriter::new(identifier) {
+      Ok(writer) => {
+        self.insert_logger(logger, level, LoggerInstance::JournaldLoggerInst(writer));
+      }
+      Err(e) =>
+        self.log_internal(
+          format!("Could not connect the {} logger to journald: {}", logger, e),
+          level::SEVERE)
+    }
   }
 
   fn add_logger(&mut self, logger:String, level: LogLevel, log_ty: LoggerOutput) {
@@ -265,17 +740,55 @@ impl LoggerTaskInfo{
     } else {
       match log_ty {
         LoggerOutput::StdoutLog => {
-          self.loggers.insert(logger, (level, LoggerInstance::StdoutLoggerInst));
+          self.insert_logger(logger, level, LoggerInstance::StdoutLoggerInst);
         }
         LoggerOutput::StderrLog => {
-          self.loggers.insert(logger, (level, LoggerInstance::StderrLoggerInst));
+          self.insert_logger(logger, level, LoggerInstance::StderrLoggerInst);
         }
         LoggerOutput::FileLog(path) => {
-          self.add_file_logger(logger, level, path);
+          self.add_file_logger(logger, level, path, false, |p| LogFile::open(p, &FileOptions::default()));
+        }
+        LoggerOutput::FileLogWithOptions(path, options) => {
+          self.add_file_logger(logger, level, path, false, |p| LogFile::open(p, &options));
+        }
+        LoggerOutput::RotatingFileLog(path, max_size, keep, options) => {
+          self.add_file_logger(
+            logger, level, path, true,
+            |p| LogFile::open_rotating(p, max_size, keep, &options));
+        }
+        #[cfg(feature = "time")]
+        LoggerOutput::TimeRotatingFileLog(pattern, period, max_age, options) => {
+          self.add_file_logger(
+            logger, level, pattern, true,
+            |p| LogFile::open_time_rotating(p, period, max_age, &options));
         }
         LoggerOutput::MultiLog(others) => {
           self.add_multi_logger(logger, level, others);
         }
+        LoggerOutput::Syslog(options) => {
+          self.add_syslog_logger(logger, level, options);
+        }
+        LoggerOutput::MemoryRing(capacity) => {
+          let ring = RefCell::new(VecDeque::with_capacity(capacity));
+          self.insert_logger(logger, level, LoggerInstance::MemoryRingLoggerInst(ring, capacity));
+        }
+        LoggerOutput::Custom(sink) => {
+          *self.sink_uses.entry(sink_key(&sink)).or_insert(0) += 1;
+          self.insert_logger(logger, level, LoggerInstance::CustomLoggerInst(sink));
+        }
+        LoggerOutput::Channel(sender) => {
+          self.insert_logger(logger, level, LoggerInstance::ChannelLoggerInst(sender));
+        }
+        LoggerOutput::Callback(callback) => {
+          self.insert_logger(logger, level, LoggerInstance::CallbackLoggerInst(callback));
+        }
+        LoggerOutput::Network(options) => {
+          self.add_network_logger(logger, level, options);
+        }
+        #[cfg(target_os = "linux")]
+        LoggerOutput::Journald(identifier) => {
+          self.add_journald_logger(logger, level, identifier);
+        }
       };
     }
   }
@@ -287,18 +800,53 @@ impl LoggerTaskInfo{
         self.add_logger(logger_name, level.unwrap_or(level::DEFAULT), log_ty);
         self.log_internal("Attempted to redirect non-existant logger", level::WARNING);
       }
-      Some((old_level, _)) => {
+      Some((old_level, old_instance, old_id)) => {
         let new_level = level.unwrap_or(old_level);
         self.add_logger(logger_name, new_level, log_ty);
+        // Let go of the old output only now, so a sink the logger is
+        // redirected to again isn't closed in between.
+        self.release(old_instance, old_id);
       }
     }
   }
 
+  fn insert_logger(&mut self, logger: String, level: LogLevel, instance: LoggerInstance) {
+    self.last_logger_id += 1;
+    let id = self.last_logger_id;
+    self.stats.name_logger(id, logger.clone());
+    self.loggers.insert(logger, (level, instance, id));
+  }
+
+  /// Drops an output which a logger no longer uses.  A custom sink is
+  /// flushed and closed as well, unless another logger still uses it.
+  fn release(&mut self, instance: LoggerInstance, id: usize) {
+    if let LoggerInstance::CustomLoggerInst(ref sink) = instance {
+      let key = sink_key(sink);
+      let in_use = match self.sink_uses.get_mut(&key) {
+        Some(uses) => {
+          *uses -= 1;
+          *uses > 0
+        }
+        None => false
+      };
+      if !in_use {
+        self.sink_uses.remove(&key);
+        if let Ok(mut sink) = sink.lock() {
+          sink.flush();
+          sink.close();
+        }
+      }
+    }
+    // Only forgotten now, so that a sink which hangs as it closes can
+    // still be named.
+    self.stats.forget_logger(id);
+  }
+
   fn disable_logger(&mut self, logger:String, log: bool) {
     let removed = self.loggers.remove(&logger);
 
     if log {
-      if let Some((_, log_inst)) = removed {
+      if let Some((_, ref log_inst, _)) = removed {
         self.log_internal(
           format!(
             "{} {} has been removed and disabled. Logger was in use.",
@@ -313,6 +861,9 @@ impl LoggerTaskInfo{
           level::DEBUG);
       }
     }
+    if let Some((_, log_inst, id)) = removed {
+      self.release(log_inst, id);
+    }
     self.disabled.insert(logger, log);
   }
 
@@ -320,66 +871,605 @@ impl LoggerTaskInfo{
     self.write_message(
       INTERNAL_LOGGER_NAME,
       level,
-      message);
+      message,
+      &[]);
   }
 }
 
-pub fn spawn_logger(rx: Receiver<LoggerMessage>) -> JoinHandle<()>{
+/// Kept by `ArtifactGlobalLib` to stop the logger task.
+pub struct TaskHandle{
+  runner: TaskRunner,
+  stats: Arc<TaskStats>,
+}
+
+enum TaskRunner{
+  /// The logger thread, a channel which disconnects once it exits,
+  /// and its queue.
+  Thread(JoinHandle<()>, Receiver<()>, Arc<MessageQueue>),
+  Inline(Arc<InlineLogger>),
+}
+
+/// Kept up to date by the logger task, and shared with whoever stops it
+/// so that there's something to report even if it doesn't finish.
+pub struct TaskStats{
+  /// Lines written to outputs, leaving out the task's own messages.
+  written: AtomicU64,
+  failed_sinks: Mutex<Vec<String>>,
+  /// The names of the task's loggers, by id.  Only changed as loggers
+  /// come and go, and only read to name a logger in the report.
+  logger_names: Mutex<HashMap<usize, String>>,
+  /// The id of the logger whose output is being written to, or which
+  /// is being flushed or closed while the task shuts down.  0 if none.
+  busy: AtomicUsize,
+}
+
+impl TaskStats{
+  fn new() -> TaskStats {
+    TaskStats{
+      written: AtomicU64::new(0),
+      failed_sinks: Mutex::new(Vec::new()),
+      logger_names: Mutex::new(HashMap::new()),
+      busy: AtomicUsize::new(0)}
+  }
+
+  fn name_logger(&self, id: usize, name: String) {
+    self.logger_names.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(id, name);
+  }
+
+  fn forget_logger(&self, id: usize) {
+    self.logger_names.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&id);
+  }
+
+  fn set_busy(&self, logger_id: usize) {
+    self.busy.store(logger_id, Ordering::Relaxed);
+  }
+
+  fn add_failed_sink(&self, logger_name: String) {
+    self.failed_sinks.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(logger_name);
+  }
+
+  /// If the task timed out, whatever logger it was busy with is counted
+  /// as failed, as it's most likely what's stuck.
+  fn report(&self, dropped: u64, timed_out: bool) -> ShutdownReport {
+    let mut failed_sinks = self.failed_sinks.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+    if timed_out {
+      let busy = self.busy.load(Ordering::Relaxed);
+      let name = self.logger_names.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).get(&busy).cloned();
+      if let Some(name) = name {
+        if !failed_sinks.contains(&name) {
+          failed_sinks.push(name);
+        }
+      }
+    }
+    ShutdownReport{
+      written: self.written.load(Ordering::Relaxed),
+      dropped,
+      failed_sinks,
+      timed_out}
+  }
+}
+
+impl TaskHandle{
+  /// Stops the logger task.  It has until the timeout to write the log
+  /// messages already sent, and to flush and close its loggers.  If it
+  /// takes longer it's left to finish on its own, and anything still
+  /// queued is dropped.
+  pub fn shut_down(self, timeout: Duration) -> ShutdownReport {
+    let deadline = Instant::now() + timeout;
+    let (finished, dropped) = match self.runner {
+      TaskRunner::Thread(thread, exited, queue) => {
+        queue.push(LoggerMessage::Shutdown(deadline));
+        let finished = wait_for_exit(&exited, deadline);
+        if finished {
+          let _ = thread.join();
+        } else {
+          queue.close();
+        }
+        (finished, queue.dropped_total())
+      }
+      TaskRunner::Inline(logger) => (wait_for_exit(&InlineLogger::shut_down(&logger), deadline), 0),
+    };
+    self.stats.report(dropped, !finished)
+  }
+}
+
+/// Waits, up to the deadline, for the sending end of the channel to be
+/// dropped.  Returns whether it was.
+fn wait_for_exit(exited: &Receiver<()>, deadline: Instant) -> bool {
+  let result = exited.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+  !matches!(result, Err(RecvTimeoutError::Timeout))
+}
+
+pub fn spawn_logger(queue: Arc<MessageQueue>, level_cache: Arc<LevelCache>) -> TaskHandle {
   //! Spawns the main logger task
-  thread::spawn(move | | logger_main(rx))
+  let stats = Arc::new(TaskStats::new());
+  let (running, exited) = channel();
+  let thread = {
+    let queue = queue.clone();
+    let stats = stats.clone();
+    thread::spawn(move | | {
+      let _running = running;
+      logger_main(queue, level_cache, stats)
+    })
+  };
+  TaskHandle{runner: TaskRunner::Thread(thread, exited, queue), stats}
+}
+
+/// Sets up the logger task for synchronous mode.
+pub fn start_inline(level_cache: Arc<LevelCache>) -> (Arc<InlineLogger>, TaskHandle) {
+  let stats = Arc::new(TaskStats::new());
+  let logger = Arc::new(InlineLogger::new(level_cache, stats.clone()));
+  (logger.clone(), TaskHandle{runner: TaskRunner::Inline(logger), stats})
+}
+
+/// Closes the queue when the logger thread exits, however it exits,
+/// so that nobody waits for room in it forever.
+struct CloseOnExit(Arc<MessageQueue>);
+
+impl Drop for CloseOnExit{
+  fn drop(&mut self) {
+    self.0.close();
+  }
+}
+
+fn logger_main(queue: Arc<MessageQueue>, level_cache: Arc<LevelCache>, stats: Arc<TaskStats>){
+  let _close_on_exit = CloseOnExit(queue.clone());
+  queue.set_consumer();
+  let mut task_info = LoggerTaskInfo::new(level_cache, stats);
+
+  while let Some((message, dropped)) = queue.pop() {
+    if dropped > 0 {
+      task_info.log_internal(
+        format!("{} messages were dropped because the logger queue was full.", dropped),
+        level::WARNING);
+    }
+    if let LoggerMessage::Shutdown(deadline) = message {
+      drain(&queue, &mut task_info, deadline);
+      break;
+    }
+    task_info.handle_message(message);
+  }
+  task_info.shut_down();
+}
+
+/// Handles whatever was sent before the queue empties or the deadline
+/// passes, then closes the queue, dropping anything left in it.
+fn drain(queue: &MessageQueue, task_info: &mut LoggerTaskInfo, deadline: Instant) {
+  while Instant::now() < deadline {
+    match queue.try_pop() {
+      // Later shutdowns have nothing left to do.
+      Some(LoggerMessage::Shutdown(_)) => {}
+      Some(message) => {
+        task_info.handle_message(message);
+      }
+      None => break
+    }
+  }
+  queue.close();
+}
+
+// For each `InlineLogger` this thread is in the middle of handling a
+// message for, the messages sent to it in the meantime.
+thread_local!(static INLINE_QUEUES: RefCell<Vec<(usize, Vec<LoggerMessage>)>> = const { RefCell::new(Vec::new()) });
+
+/// The logger task for synchronous mode, where messages are handled on
+/// the thread which sends them instead of on a thread of its own.
+pub struct InlineLogger{
+  task_info: Mutex<Option<LoggerTaskInfo>>,
+  stopping: AtomicBool,
+}
+
+impl InlineLogger{
+  fn new(level_cache: Arc<LevelCache>, stats: Arc<TaskStats>) -> InlineLogger {
+    InlineLogger{
+      task_info: Mutex::new(Some(LoggerTaskInfo::new(level_cache, stats))),
+      stopping: AtomicBool::new(false)}
+  }
+
+  /// Stops handling messages, and closes the loggers on a thread of
+  /// their own so the caller can give up on a sink which hangs.  That
+  /// thread also waits for any message still being handled, as another
+  /// thread may be stuck writing it.  The returned channel disconnects
+  /// once the loggers are closed.
+  fn shut_down(logger: &Arc<InlineLogger>) -> Receiver<()> {
+    logger.stopping.store(true, Ordering::SeqCst);
+    let logger = logger.clone();
+    let (running, exited) = channel();
+    thread::spawn(move | | {
+      let _running = running;
+      let stopped = match logger.task_info.lock() {
+        Ok(mut guard) => guard.take(),
+        Err(poisoned) => poisoned.into_inner().take()
+      };
+      if let Some(task_info) = stopped {
+        task_info.shut_down();
+      }
+    });
+    exited
+  }
+
+  /// Handles a message before returning.  Returns false once the
+  /// logger has been stopped.
+  pub fn handle(&self, message: LoggerMessage) -> bool {
+    // A sink or formatter which logs while handling a message would
+    // deadlock on the lock, so those messages are queued and handled
+    // afterwards.  Anything waiting on a reply can't wait that long,
+    // so it's dropped and the sender sees no answer.
+    let id = self.id();
+    let message = INLINE_QUEUES.with(|queues| {
+      match queues.borrow_mut().iter_mut().find(|&&mut (queue_id, _)| queue_id == id) {
+        Some(&mut (_, ref mut queue)) => {
+          if !message.expects_reply() {
+            queue.push(message);
+          }
+          None
+        }
+        None => Some(message)
+      }
+    });
+    let message = match message {
+      Some(message) => message,
+      None => return true
+    };
+    if self.stopping.load(Ordering::SeqCst) {
+      return false;
+    }
+
+    let mut guard = match self.task_info.lock() {
+      Ok(guard) => guard,
+      Err(poisoned) => poisoned.into_inner()
+    };
+    let running = match *guard {
+      Some(ref mut task_info) => handle_with_queue(id, task_info, message),
+      None => return false
+    };
+    if !running {
+      // The loggers are flushed and closed as when the logger thread
+      // exits.  That's done unlocked, in case a sink logs as it closes.
+      let stopped = guard.take();
+      drop(guard);
+      if let Some(task_info) = stopped {
+        task_info.shut_down();
+      }
+    }
+    true
+  }
+
+  /// Tells this logger apart from others in `INLINE_QUEUES`.
+  fn id(&self) -> usize {
+    self as *const InlineLogger as usize
+  }
+}
+
+/// Handles a message along with any sent while handling it.
+fn handle_with_queue(id: usize, task_info: &mut LoggerTaskInfo, message: LoggerMessage) -> bool {
+  INLINE_QUEUES.with(|queues| queues.borrow_mut().push((id, Vec::new())));
+
+  let mut pending = vec!(message);
+  let mut running = true;
+  while running && !pending.is_empty() {
+    for message in pending {
+      running = running && task_info.handle_message(message);
+    }
+    // Anything handled in between has removed its own queue again,
+    // so this logger's is the last one.
+    pending = INLINE_QUEUES.with(|queues| {
+      queues.borrow_mut().last_mut().map_or(Vec::new(), |&mut (_, ref mut queue)| mem::take(queue))
+    });
+  }
+
+  INLINE_QUEUES.with(|queues| queues.borrow_mut().pop());
+  running
 }
 
-fn logger_main(rx: Receiver<LoggerMessage>){
-  let mut task_info = LoggerTaskInfo::new();
+impl LoggerMessage{
+  fn expects_reply(&self) -> bool {
+    matches!(*self, LoggerMessage::IsLogEnabled(..) | LoggerMessage::Snapshot(..) | LoggerMessage::Flush(..))
+  }
+}
 
-  loop {
-    match rx.recv() {
-      Ok(LoggerMessage::LogMessage(logger, level, message)) => {
-        task_info.write_message(logger.as_ref(), level, message);
+impl LoggerTaskInfo{
+  /// Handles one message.  Returns false once the task should stop.
+  fn handle_message(&mut self, message: LoggerMessage) -> bool {
+    match message {
+      LoggerMessage::LogMessage(entry) => {
+        self.write_log_entry(&entry);
       }
 
-      Ok(LoggerMessage::NewLogger(logger, level, output)) =>
-        task_info.add_logger(logger, level, output),
+      LoggerMessage::NewLogger(logger, level, output) => {
+        self.add_logger(logger, level, output);
+        self.finish_level_update();
+      }
 
-      Ok(LoggerMessage::PoisonPill) => {
-        break;
+      LoggerMessage::Shutdown(_) => {
+        return false;
       }
 
-      Ok(LoggerMessage::RegisterLevelString(level, string)) => {
-        task_info.level_strings.insert(level, string);
+      LoggerMessage::RegisterLevelString(level, string) => {
+        self.level_strings.insert(level, string);
       }
 
-      Ok(LoggerMessage::Disable(name, log)) => {
-        task_info.disable_logger(name, log);
+      LoggerMessage::Disable(name, log) => {
+        self.disable_logger(name, log);
+        self.finish_level_update();
       }
 
-      Ok(LoggerMessage::RedirectLogger(logger, level_opt, output)) => {
-        task_info.redirect_logger(logger, level_opt, output);
+      LoggerMessage::RedirectLogger(logger, level_opt, output) => {
+        self.redirect_logger(logger, level_opt, output);
+        self.finish_level_update();
       }
 
-      Ok(LoggerMessage::SetFormatter(logger, formatter)) => {
-        task_info.formatters.insert(logger, formatter);
+      LoggerMessage::SetFormatter(logger, formatter) => {
+        self.formatters.insert(logger, formatter);
       }
 
-      Ok(LoggerMessage::SetDefaultFormatter(formatter)) => {
-        task_info.default_formatter = formatter
+      LoggerMessage::SetDefaultFormatter(formatter) => {
+        self.default_formatter = formatter
       }
 
-      #[cfg(feature = "log")]
-      Ok(LoggerMessage::IsLogEnabled(logger, level, send_reply)) => {
-        let enabled = task_info.loggers.get(&logger)
-          .map(
-            |&(logger_level, _)| level < logger_level)
-          .unwrap_or(task_info.default_logger != DefaultLogTarget::NoDefault);
+      LoggerMessage::IsLogEnabled(logger, level, send_reply) => {
+        let _ = send_reply.send(self.accepts(&logger, level));
+      }
+
+      LoggerMessage::SetDefaultLogTarget(target) => {
+        self.default_logger = target;
+        self.finish_level_update();
+      }
+
+      LoggerMessage::ReopenFiles => {
+        self.reopen_files();
+      }
 
-        let _ = send_reply.send(enabled);
+      LoggerMessage::Snapshot(logger, send_reply) => {
+        let _ = send_reply.send(self.snapshot(&logger));
       }
 
-      Ok(LoggerMessage::SetDefaultLogTarget(target)) => {
-        task_info.default_logger = target
+      LoggerMessage::Flush(logger, sync, send_reply) => {
+        match logger {
+          Some(logger) => self.flush_logger(&logger, sync, &mut Vec::new()),
+          None => {
+            for (name, (_, instance, _)) in &self.loggers {
+              instance.flush(name, sync, self);
+            }
+          }
+        }
+        let _ = send_reply.send(());
       }
+    }
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+  use std::sync::mpsc::{channel, Receiver, Sender};
+  use std::thread;
+  use std::time::{Duration, Instant};
+
+  use format::{JsonMessageFormatter, SimpleMessageFormatter};
+  use pattern::PatternFormatter;
+  use internal::levels::LevelCache;
+  use internal::queue::MessageQueue;
+  use level;
+  use level::LogLevel;
+  use logger::{LoggerOutput, OverflowPolicy, ShutdownReport};
+  use {LogSink, MessageFormatter};
+  use super::{LoggerTaskInfo, LoggerMessage, LogEntry, TaskStats, spawn_logger, start_inline};
+
+  fn task() -> LoggerTaskInfo {
+    LoggerTaskInfo::new(Arc::new(LevelCache::new()), Arc::new(TaskStats::new()))
+  }
+
+  /// Hands a message to the task, marking the level cache as out of
+  /// date first where `Connection::send` would.
+  fn send(task: &mut LoggerTaskInfo, message: LoggerMessage) {
+    match message {
+      LoggerMessage::NewLogger(..) |
+      LoggerMessage::RedirectLogger(..) |
+      LoggerMessage::Disable(..) |
+      LoggerMessage::SetDefaultLogTarget(..) =>
+        task.level_cache.begin_update(),
+      _ => {}
+    }
+    task.handle_message(message);
+  }
+
+  /// Sets up `M1` forwarding to `M2` forwarding to a memory ring `Leaf`,
+  /// with `M1` using the given formatter and the others the simple one.
+  fn chained_task(first_formatter: Box<MessageFormatter>) -> LoggerTaskInfo {
+    let mut task = task();
+    send(&mut task, LoggerMessage::RegisterLevelString(level::INFO, "INFO".to_string()));
+    send(&mut task, LoggerMessage::NewLogger("Leaf".to_string(), level::DEFAULT, LoggerOutput::MemoryRing(10)));
+    send(&mut task, 
+      LoggerMessage::NewLogger("M2".to_string(), level::DEFAULT, LoggerOutput::MultiLog(vec!["Leaf".to_string()])));
+    send(&mut task, 
+      LoggerMessage::NewLogger("M1".to_string(), level::DEFAULT, LoggerOutput::MultiLog(vec!["M2".to_string()])));
+    send(&mut task, LoggerMessage::SetFormatter("M1".to_string(), first_formatter));
+    send(&mut task, LoggerMessage::SetFormatter("M2".to_string(), Box::new(SimpleMessageFormatter)));
+    send(&mut task, LoggerMessage::SetFormatter("Leaf".to_string(), Box::new(SimpleMessageFormatter)));
+    task
+  }
+
+  fn written(task: &mut LoggerTaskInfo, logger: &str) -> Vec<String> {
+    let (send, recv) = channel();
+    task.handle_message(LoggerMessage::Snapshot(logger.to_string(), send));
+    recv.recv().unwrap()
+  }
+
+  #[test]
+  fn multi_loggers_prefix_forwarded_messages() {
+    let mut task = chained_task(Box::new(SimpleMessageFormatter));
+    send(&mut task, LoggerMessage::LogMessage(LogEntry::new("M1", level::INFO, "hi".to_string(), Vec::new())));
+
+    assert_eq!(written(&mut task, "Leaf"), vec!["[Leaf] from [M2] from [M1] -- INFO: hi".to_string()]);
+  }
+
+  #[test]
+  fn forwarding_aware_formatter_gets_no_prefixes() {
+    let mut task = chained_task(Box::new(JsonMessageFormatter));
+    send(&mut task, LoggerMessage::LogMessage(LogEntry::new("M1", level::INFO, "hi".to_string(), Vec::new())));
+
+    let written = written(&mut task, "Leaf");
+    assert_eq!(written.len(), 1);
+    assert!(written[0].starts_with('{'), "{}", written[0]);
+    assert!(written[0].ends_with(
+      r#""forwarded":[{"via":"multi","from":"M1","to":"M2"},{"via":"multi","from":"M2","to":"Leaf"}]}"#),
+      "{}", written[0]);
+  }
+
+  #[test]
+  fn published_levels_follow_the_loggers() {
+    let mut task = chained_task(Box::new(SimpleMessageFormatter));
+    send(&mut task, LoggerMessage::NewLogger("Quiet".to_string(), level::WARNING, LoggerOutput::MemoryRing(1)));
+    send(&mut task, 
+      LoggerMessage::NewLogger("Both".to_string(), level::INFO,
+                               LoggerOutput::MultiLog(vec!["Quiet".to_string(), "Leaf".to_string()])));
+    let cache = task.level_cache.clone();
+    let mut local = None;
+
+    assert_eq!(cache.accepts(&mut local, "Quiet", level::WARNING), Some(true));
+    assert_eq!(cache.accepts(&mut local, "Quiet", level::INFO), Some(false));
+    // A multi-logger passes on what any of its loggers would write,
+    // but no more than its own level allows.
+    assert_eq!(cache.accepts(&mut local, "Both", level::INFO), Some(true));
+    assert_eq!(cache.accepts(&mut local, "Both", level::DEBUG), Some(false));
+    // Messages to missing loggers get reported, so the task has to see them.
+    assert_eq!(cache.accepts(&mut local, "Missing", level::VERBOSE), None);
+
+    send(&mut task, LoggerMessage::Disable("Quiet".to_string(), false));
+    assert_eq!(cache.accepts(&mut local, "Quiet", level::WTF), Some(false));
+  }
+
+  /// Notes down what it's asked to do.
+  struct RecordingSink(Arc<Mutex<Vec<String>>>);
+
+  impl LogSink for RecordingSink {
+    fn write(&mut self, _: &str, _: LogLevel, message: &str) {
+      self.0.lock().unwrap().push(format!("write {}", message));
+    }
+
+    fn close(&mut self) {
+      self.0.lock().unwrap().push("close".to_string());
+    }
+  }
+
+  fn recording_sink() -> (LoggerOutput, Arc<Mutex<Vec<String>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    (LoggerOutput::Custom(Arc::new(Mutex::new(RecordingSink(events.clone())))), events)
+  }
 
-      Err(_) => break,
+  fn log_message(logger: &str, message: &str) -> LoggerMessage {
+    LoggerMessage::LogMessage(LogEntry::new(logger, level::INFO, message.to_string(), Vec::new()))
+  }
+
+  #[test]
+  fn sink_is_kept_open_when_redirected_to_itself() {
+    let (output, events) = recording_sink();
+    let mut task = task();
+    send(&mut task, LoggerMessage::NewLogger("Sink".to_string(), level::DEFAULT, output.clone()));
+    send(&mut task, LoggerMessage::SetFormatter("Sink".to_string(), Box::new(PatternFormatter::new("{message}").unwrap())));
+    send(&mut task, log_message("Sink", "one"));
+    send(&mut task, LoggerMessage::RedirectLogger("Sink".to_string(), Some(level::VERBOSE), output));
+    send(&mut task, log_message("Sink", "two"));
+    send(&mut task, LoggerMessage::Disable("Sink".to_string(), false));
+
+    assert_eq!(*events.lock().unwrap(), vec!["write one", "write two", "close"]);
+  }
+
+  #[test]
+  fn shared_sink_is_closed_with_its_last_logger() {
+    let (output, events) = recording_sink();
+    let mut task = task();
+    for name in &["A", "B"] {
+      send(&mut task, LoggerMessage::NewLogger(name.to_string(), level::DEFAULT, output.clone()));
     }
+    send(&mut task, LoggerMessage::Disable("A".to_string(), false));
+    assert!(events.lock().unwrap().is_empty());
+
+    task.shut_down();
+    assert_eq!(*events.lock().unwrap(), vec!["close"]);
+  }
+
+  /// Hangs in `write` until released, after saying it got there.
+  struct StuckSink{
+    entered: Sender<()>,
+    release: Receiver<()>,
+  }
+
+  impl LogSink for StuckSink {
+    fn write(&mut self, _: &str, _: LogLevel, _: &str) {
+      let _ = self.entered.send(());
+      let _ = self.release.recv();
+    }
+  }
+
+  /// A stuck sink, a receiver told when it gets stuck, and a sender
+  /// which releases it.
+  fn stuck_sink() -> (LoggerOutput, Receiver<()>, Sender<()>) {
+    let (entered, entered_recv) = channel();
+    let (release_send, release) = channel();
+    let sink = StuckSink{entered, release};
+    (LoggerOutput::Custom(Arc::new(Mutex::new(sink))), entered_recv, release_send)
+  }
+
+  fn log_to(logger: &str) -> LoggerMessage {
+    log_message(logger, "hi")
+  }
+
+  #[test]
+  fn shutting_down_writes_what_was_sent() {
+    let queue = Arc::new(MessageQueue::new(None, OverflowPolicy::Block));
+    let handle = spawn_logger(queue.clone(), Arc::new(LevelCache::new()));
+    queue.push(LoggerMessage::NewLogger("Ring".to_string(), level::DEFAULT, LoggerOutput::MemoryRing(10)));
+    queue.push(LoggerMessage::NewLogger("Quiet".to_string(), level::WARNING, LoggerOutput::MemoryRing(10)));
+    queue.push(LoggerMessage::Disable("Off".to_string(), false));
+    for _ in 0..3 {
+      queue.push(log_to("Ring"));
+    }
+    // Neither of these gets written anywhere.
+    queue.push(log_to("Quiet"));
+    queue.push(log_to("Off"));
+
+    let report = handle.shut_down(Duration::from_secs(5));
+    assert_eq!(report, ShutdownReport{written: 3, dropped: 0, failed_sinks: Vec::new(), timed_out: false});
+    assert!(!queue.push(log_to("Ring")));
+  }
+
+  #[test]
+  fn shutting_down_gives_up_on_a_stuck_sink() {
+    let (output, entered, release) = stuck_sink();
+    let queue = Arc::new(MessageQueue::new(None, OverflowPolicy::Block));
+    let handle = spawn_logger(queue.clone(), Arc::new(LevelCache::new()));
+    queue.push(LoggerMessage::NewLogger("Stuck".to_string(), level::DEFAULT, output));
+    queue.push(log_to("Stuck"));
+    entered.recv().unwrap();
+    queue.push(log_to("Stuck"));
+
+    let report = handle.shut_down(Duration::from_millis(200));
+    assert!(report.timed_out);
+    assert_eq!(report.failed_sinks, vec!["Stuck".to_string()]);
+    assert_eq!(report.dropped, 1);
+    let _ = release.send(());
+  }
+
+  #[test]
+  fn synchronous_shutdown_does_not_wait_for_a_stuck_sink() {
+    let (output, entered, release) = stuck_sink();
+    let (logger, handle) = start_inline(Arc::new(LevelCache::new()));
+    logger.handle(LoggerMessage::NewLogger("Stuck".to_string(), level::DEFAULT, output));
+    {
+      let logger = logger.clone();
+      thread::spawn(move || logger.handle(log_to("Stuck")));
+    }
+    entered.recv().unwrap();
+
+    let started = Instant::now();
+    let report = handle.shut_down(Duration::from_millis(200));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(report.timed_out);
+    assert_eq!(report.failed_sinks, vec!["Stuck".to_string()]);
+    assert!(!logger.handle(log_to("Stuck")));
+    let _ = release.send(());
   }
 }
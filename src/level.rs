@@ -35,3 +35,121 @@ pub const VERBOSE:  LogLevel = 800;
 
 pub const DEFAULT: LogLevel = DEBUG;
 pub const INTERNAL_EXTREME_FAIL: LogLevel = WTF;
+
+/// Not a level messages are logged at.  As a logger's level, or as the
+/// `STATIC_MAX_LEVEL`, it means no messages are logged.
+pub const OFF:      LogLevel = 0;
+
+/// The least severe level which is compiled in.  Messages at less severe
+/// levels are discarded by `Logger` before doing any work, and since the
+/// check is on a constant the compiler removes the call entirely.
+///
+/// Set with the `max-level-*` features, e.g. `max-level-info`, and with
+/// the `release-max-level-*` features, which only count in builds without
+/// debug assertions and then take priority over every `max-level-*`
+/// feature, even a more restrictive one.  If several features of the
+/// same kind are given, the most restrictive one wins.  The `disabled`
+/// feature turns everything off.
+pub const STATIC_MAX_LEVEL: LogLevel =
+  if cfg!(any(feature = "disabled", all(not(debug_assertions), feature = "release-max-level-off"))) {
+    OFF
+  } else if cfg!(all(not(debug_assertions), feature = "release-max-level-wtf")) {
+    WTF
+  } else if cfg!(all(not(debug_assertions), feature = "release-max-level-critical")) {
+    CRITICAL
+  } else if cfg!(all(not(debug_assertions), feature = "release-max-level-severe")) {
+    SEVERE
+  } else if cfg!(all(not(debug_assertions), feature = "release-max-level-warning")) {
+    WARNING
+  } else if cfg!(all(not(debug_assertions), feature = "release-max-level-info")) {
+    INFO
+  } else if cfg!(all(not(debug_assertions), feature = "release-max-level-debug")) {
+    DEBUG
+  } else if cfg!(all(not(debug_assertions), feature = "release-max-level-trace")) {
+    TRACE
+  } else if cfg!(all(not(debug_assertions), feature = "release-max-level-verbose")) {
+    VERBOSE
+  } else {
+    MAX_LEVEL_FEATURE
+  };
+
+const MAX_LEVEL_FEATURE: LogLevel =
+  if cfg!(feature = "max-level-off") {
+    OFF
+  } else if cfg!(feature = "max-level-wtf") {
+    WTF
+  } else if cfg!(feature = "max-level-critical") {
+    CRITICAL
+  } else if cfg!(feature = "max-level-severe") {
+    SEVERE
+  } else if cfg!(feature = "max-level-warning") {
+    WARNING
+  } else if cfg!(feature = "max-level-info") {
+    INFO
+  } else if cfg!(feature = "max-level-debug") {
+    DEBUG
+  } else if cfg!(feature = "max-level-trace") {
+    TRACE
+  } else if cfg!(feature = "max-level-verbose") {
+    VERBOSE
+  } else {
+    u32::MAX
+  };
+
+/// Whether messages at the level are compiled in at all.
+// Without a max level feature the comparison always passes, which is
+// the point rather than a mistake.
+#[allow(clippy::absurd_extreme_comparisons)]
+#[inline(always)]
+pub fn static_enabled(level: LogLevel) -> bool {
+  STATIC_MAX_LEVEL != OFF && level <= STATIC_MAX_LEVEL
+}
+
+/// Maps a level to the closest syslog severity (0 being emergency,
+/// 7 being debug).  Levels which fall between the named levels get
+/// the severity of the next less severe named level.
+pub fn to_syslog_severity(level: LogLevel) -> u8 {
+  if level <= WTF {
+    1
+  } else if level <= CRITICAL {
+    2
+  } else if level <= SEVERE {
+    3
+  } else if level <= WARNING {
+    4
+  } else if level < INFO {
+    5
+  } else if level <= INFO {
+    6
+  } else {
+    7
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn named_levels_map_to_syslog_severities() {
+    assert_eq!(to_syslog_severity(WTF), 1);
+    assert_eq!(to_syslog_severity(CRITICAL), 2);
+    assert_eq!(to_syslog_severity(SEVERE), 3);
+    assert_eq!(to_syslog_severity(WARNING), 4);
+    assert_eq!(to_syslog_severity(INFO), 6);
+    assert_eq!(to_syslog_severity(DEBUG), 7);
+    assert_eq!(to_syslog_severity(TRACE), 7);
+    assert_eq!(to_syslog_severity(VERBOSE), 7);
+  }
+
+  #[test]
+  fn levels_between_names_take_the_less_severe_severity() {
+    assert_eq!(to_syslog_severity(1), 1);
+    assert_eq!(to_syslog_severity(WTF + 1), 2);
+    assert_eq!(to_syslog_severity(SEVERE + 50), 4);
+    // Between WARNING and INFO is the only place notice gets used.
+    assert_eq!(to_syslog_severity(WARNING + 1), 5);
+    assert_eq!(to_syslog_severity(INFO - 1), 5);
+    assert_eq!(to_syslog_severity(INFO + 1), 7);
+  }
+}
@@ -21,7 +21,7 @@
  *
  */
 
-#[macro_use]
+#[cfg_attr(not(feature = "disabled"), macro_use)]
 extern crate lazy_static;
 
 #[cfg(feature = "time")]
@@ -30,38 +30,241 @@ extern crate time;
 #[cfg(feature = "log")]
 extern crate log;
 
-use std::thread::JoinHandle;
+#[cfg(target_os = "linux")]
+extern crate libc;
+
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use internal::queue::MessageQueue;
+#[cfg(target_os = "linux")]
+use std::io;
 
 pub use level::LogLevel;
 pub use level::{WTF, CRITICAL, SEVERE, WARNING, DEBUG, INFO, TRACE, VERBOSE};
-pub use logger::{Logger, LoggerOutput};
-pub use format::{MessageFormatter, SimpleMessageFormatter, NoForwardingIndicationFormatter};
+pub use logger::{Logger, LoggerOutput, FileOptions, SyslogOptions, SyslogTransport, SyslogFormat};
+pub use logger::{NetworkOptions, NetworkProtocol, OverflowPolicy, ShutdownReport};
+pub use sink::{LogSink, LogRecord, RecordCallback};
+pub use format::{MessageFormatter, MessageInfo, Forwarding};
+pub use format::{SimpleMessageFormatter, JsonMessageFormatter, LogfmtMessageFormatter, NoForwardingIndicationFormatter};
+pub use format::ColourFormatter;
+pub use field::{Field, FieldValue};
+pub use pattern::{PatternFormatter, PatternError};
+pub use location::Location;
 #[cfg(feature = "time")]
 pub use format::ZuluTimeMessageFormatter;
+#[cfg(feature = "time")]
+pub use logger::RotationPeriod;
 
 pub mod level;
 pub mod logger;
 pub mod format;
+pub mod sink;
+pub mod field;
+pub mod pattern;
+pub mod location;
 mod internal;
 
-/// Used to initialize and clean up the logger library
+/// How long dropping an `Artifact` or `ArtifactGlobalLib` waits for
+/// the logger task.
+const DROP_SHUTDOWN_TIMEOUT_SECS: u64 = 5;
+
+/// A logger task, along with the loggers made in it, independent of
+/// the global one and of any other `Artifact`.  Loggers are made in it
+/// with `Logger::new_in` and friends.  Dropping it shuts down as
+/// `shutdown` does, waiting at most five seconds.
+pub struct Artifact {
+  connection: internal::comm::Connection,
+  handle: Option<internal::task::TaskHandle>
+}
+
+impl Artifact{
+
+  /// Starts a logger task on a thread of its own, or runs it on the
+  /// logging threads if the `synchronous` feature is on.
+  pub fn new() -> Artifact {
+    Artifact::start(default_queue())
+  }
+
+  /// As `ArtifactGlobalLib::init_bounded`.
+  pub fn new_bounded(capacity: usize, policy: OverflowPolicy) -> Artifact {
+    Artifact::start(Some(MessageQueue::new(Some(capacity), policy)))
+  }
+
+  /// As `ArtifactGlobalLib::init_synchronous`.
+  pub fn new_synchronous() -> Artifact {
+    Artifact::start(None)
+  }
+
+  fn start(queue: Option<MessageQueue>) -> Artifact {
+    let (connection, handle) = internal::comm::start_task(queue);
+    Artifact{connection, handle}
+  }
+
+  /// Sets the formatter used by loggers in this `Artifact` which
+  /// don't have one of their own.
+  pub fn set_default_formatter(&self, fmtr: Box<MessageFormatter>) {
+    self.connection.send(internal::task::LoggerMessage::SetDefaultFormatter(fmtr));
+  }
+
+  /// Waits until every message sent before from this thread has been
+  /// written, and every output has been flushed.
+  pub fn flush(&self) {
+    self.flush_all(false);
+  }
+
+  /// As `flush`, but also waits for log files to be written to disk.
+  pub fn flush_and_sync(&self) {
+    self.flush_all(true);
+  }
+
+  fn flush_all(&self, sync: bool) {
+    let (tx, rx) = channel();
+    self.connection.send(internal::task::LoggerMessage::Flush(None, sync, tx));
+    let _ = rx.recv();
+  }
+
+  /// Tells the logger task to close and reopen every log file.
+  pub fn reopen_files(&self) {
+    self.connection.send(internal::task::LoggerMessage::ReopenFiles);
+  }
+
+  /// As `ArtifactGlobalLib::shutdown`.  Loggers made in this `Artifact`
+  /// can't log afterwards.
+  pub fn shutdown(mut self, timeout: Duration) -> ShutdownReport {
+    match self.handle.take() {
+      Some(handle) => handle.shut_down(timeout),
+      None => ShutdownReport::default()
+    }
+  }
+}
+
+impl Default for Artifact {
+  fn default() -> Artifact {
+    Artifact::new()
+  }
+}
+
+impl Drop for Artifact {
+  fn drop(&mut self) {
+    if let Some(handle) = self.handle.take() {
+      handle.shut_down(Duration::from_secs(DROP_SHUTDOWN_TIMEOUT_SECS));
+    }
+  }
+}
+
+/// Used to initialize and clean up the logger library.  This starts
+/// the global `Artifact`, which loggers made with `Logger::new` and
+/// friends use.  Dropping it shuts down as `shutdown` does, waiting at
+/// most five seconds, after which the library can be initialized again.
 pub struct ArtifactGlobalLib {
-  handle: Option<JoinHandle<()>>
+  artifact: Option<Artifact>
 }
 
 impl ArtifactGlobalLib{
 
+  /// Starts the logger task on a thread of its own, or runs it on the
+  /// logging threads if the `synchronous` feature is on.
   pub fn init() -> ArtifactGlobalLib {
-    let handle = internal::comm::init_global_task();
-    ArtifactGlobalLib{handle: handle}
+    ArtifactGlobalLib::start(default_queue())
+  }
+
+  /// Starts the logger task with a queue which holds at most `capacity`
+  /// messages, instead of one which grows without limit.  The policy
+  /// decides what happens to messages sent while it's full.  How many
+  /// messages were dropped is logged by the internal logger once the
+  /// queue has room again.  Ignores the `synchronous` feature.
+  ///
+  /// A capacity of 0 is taken as 1.  Messages logged on the logger
+  /// thread itself, by a `Custom` or `Callback` output, are dropped if
+  /// the queue is full whatever the policy, as waiting for room there
+  /// would never end.
+  pub fn init_bounded(capacity: usize, policy: OverflowPolicy) -> ArtifactGlobalLib {
+    ArtifactGlobalLib::start(Some(MessageQueue::new(Some(capacity), policy)))
+  }
+
+  /// Runs the logger task on the threads which log, instead of starting
+  /// a thread for it.  Each message is written before the logging call
+  /// returns, so nothing is lost if the process dies right after, but
+  /// threads wait for each other's writes.
+  pub fn init_synchronous() -> ArtifactGlobalLib {
+    ArtifactGlobalLib::start(None)
+  }
+
+  /// Does nothing if the library is already initialized.
+  fn start(queue: Option<MessageQueue>) -> ArtifactGlobalLib {
+    let artifact = internal::comm::init_global_task(queue)
+      .map(|(connection, handle)| Artifact{connection, handle});
+    ArtifactGlobalLib{artifact}
+  }
+
+  /// Waits until every message sent before from this thread has been
+  /// written, and every output has been flushed.
+  pub fn flush(&self) {
+    flush_all(false);
+  }
+
+  /// As `flush`, but also waits for log files to be written to disk.
+  pub fn flush_and_sync(&self) {
+    flush_all(true);
+  }
+
+  /// Tells the logger task to close and reopen every log file.
+  /// Use this after an external tool such as logrotate has moved
+  /// the files, so that logging moves on to the new files.
+  pub fn reopen_files(&self) {
+    internal::comm::send_logger_message(internal::task::LoggerMessage::ReopenFiles);
+  }
+
+  /// Stops the logger task, waiting until every log message already
+  /// sent, from any thread, has been written and every logger has been
+  /// flushed and closed, or until the timeout passes.  Past that point
+  /// the task is left behind and whatever is still queued is dropped,
+  /// so a sink which hangs can't hang the process.  The library can be
+  /// initialized again afterwards.
+  pub fn shutdown(mut self, timeout: Duration) -> ShutdownReport {
+    match self.artifact.take() {
+      Some(artifact) => {
+        internal::comm::clear_global_task();
+        artifact.shutdown(timeout)
+      }
+      None => ShutdownReport::default()
+    }
+  }
+
+  /// Installs a SIGHUP handler which reopens all log files,
+  /// as `reopen_files` does, whenever the signal arrives.
+  /// Installing the handler more than once has no further effect.
+  #[cfg(target_os = "linux")]
+  pub fn reopen_files_on_sighup(&self) -> io::Result<()> {
+    if cfg!(feature = "disabled") {
+      return Ok(());
+    }
+    internal::signal::install_sighup_handler()
+  }
+}
+
+/// An unbounded queue, or none at all if the `synchronous` feature
+/// is on.
+fn default_queue() -> Option<MessageQueue> {
+  if cfg!(feature = "synchronous") {
+    None
+  } else {
+    Some(MessageQueue::new(None, OverflowPolicy::Block))
   }
 }
 
+fn flush_all(sync: bool) {
+  let (tx, rx) = channel();
+  internal::comm::send_logger_message(internal::task::LoggerMessage::Flush(None, sync, tx));
+  let _ = rx.recv();
+}
+
 impl Drop for ArtifactGlobalLib {
   fn drop(&mut self) {
-    if let Some(thread_handle) = self.handle.take() {
-      internal::comm::stop_global_task();
-      let _ = thread_handle.join();
+    // The artifact itself shuts down as it's dropped.
+    if self.artifact.is_some() {
+      internal::comm::clear_global_task();
     }
   }
 }
@@ -69,7 +272,11 @@ impl Drop for ArtifactGlobalLib {
 #[cfg(feature = "log")]
 pub fn setup_log_crate_support() -> Result<(), log::SetLoggerError> {
   log::set_logger(|max_log_level| {
-    max_log_level.set(log::LogLevelFilter::Trace);
+    if cfg!(feature = "disabled") {
+      max_log_level.set(log::LogLevelFilter::Off);
+    } else {
+      max_log_level.set(log::LogLevelFilter::Trace);
+    }
     Box::new(logger::ArtifactDelegateLog)
   })
 }
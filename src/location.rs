@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+//! Where in the source a message was logged, and the macros which
+//! record it.
+
+/// A place in the source code, as given by `file!()`, `line!()` and
+/// `module_path!()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location{
+  pub file: &'static str,
+  pub line: u32,
+  pub module_path: &'static str,
+}
+
+impl Location{
+  pub fn new(file: &'static str, line: u32, module_path: &'static str) -> Location {
+    Location{file, line, module_path}
+  }
+}
+
+/// Logs a formatted message at the given level, recording where it was
+/// logged from: `artifact_log!(logger, level::INFO, "x = {}", x)`.  The
+/// message is only formatted if it would be written.
+#[macro_export]
+macro_rules! artifact_log {
+  ($logger:expr, $level:expr, $($arg:tt)+) => (
+    $logger.log_at(
+      $level,
+      $crate::Location::new(file!(), line!(), module_path!()),
+      format_args!($($arg)+))
+  )
+}
+
+/// `artifact_log!` at the WTF level.
+#[macro_export]
+macro_rules! artifact_wtf {
+  ($logger:expr, $($arg:tt)+) => ($crate::artifact_log!($logger, $crate::level::WTF, $($arg)+))
+}
+
+/// `artifact_log!` at the CRITICAL level.
+#[macro_export]
+macro_rules! artifact_critical {
+  ($logger:expr, $($arg:tt)+) => ($crate::artifact_log!($logger, $crate::level::CRITICAL, $($arg)+))
+}
+
+/// `artifact_log!` at the SEVERE level.
+#[macro_export]
+macro_rules! artifact_severe {
+  ($logger:expr, $($arg:tt)+) => ($crate::artifact_log!($logger, $crate::level::SEVERE, $($arg)+))
+}
+
+/// `artifact_log!` at the WARNING level.
+#[macro_export]
+macro_rules! artifact_warning {
+  ($logger:expr, $($arg:tt)+) => ($crate::artifact_log!($logger, $crate::level::WARNING, $($arg)+))
+}
+
+/// `artifact_log!` at the INFO level.
+#[macro_export]
+macro_rules! artifact_info {
+  ($logger:expr, $($arg:tt)+) => ($crate::artifact_log!($logger, $crate::level::INFO, $($arg)+))
+}
+
+/// `artifact_log!` at the DEBUG level.
+#[macro_export]
+macro_rules! artifact_debug {
+  ($logger:expr, $($arg:tt)+) => ($crate::artifact_log!($logger, $crate::level::DEBUG, $($arg)+))
+}
+
+/// `artifact_log!` at the TRACE level.
+#[macro_export]
+macro_rules! artifact_trace {
+  ($logger:expr, $($arg:tt)+) => ($crate::artifact_log!($logger, $crate::level::TRACE, $($arg)+))
+}
+
+/// `artifact_log!` at the VERBOSE level.
+#[macro_export]
+macro_rules! artifact_verbose {
+  ($logger:expr, $($arg:tt)+) => ($crate::artifact_log!($logger, $crate::level::VERBOSE, $($arg)+))
+}
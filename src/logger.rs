@@ -23,9 +23,15 @@
 
 use level;
 use level::LogLevel;
-use internal::comm::send_logger_message;
-use internal::task::{LoggerMessage, DefaultLogTarget};
+use internal::comm::{self, send_logger_message};
+use internal::task::{LoggerMessage, LogEntry, DefaultLogTarget};
 use std::path::PathBuf;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Sender, channel};
+#[cfg(feature = "time")]
+use std::time::Duration;
 
 #[cfg(feature = "log")]
 pub use self::logsupport::*;
@@ -33,22 +39,45 @@ pub use self::logsupport::*;
 use internal::task::INTERNAL_LOGGER_NAME;
 
 use MessageFormatter;
+use field::{Field, FieldValue};
+use location::Location;
+use {LogSink, LogRecord, RecordCallback, Artifact};
 
 /// A logger within the Artifact logging library.
 /// This struct is somewhat similar to an address.
 /// The struct itself only stores the name of the
-/// logger. However, the initialization functions tell
+/// logger, and which `Artifact` it belongs to if it isn't
+/// the global one. However, the initialization functions tell
 /// the backend what kind of logger it is, what level it logs
 /// at, and other interesting information.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Logger{
-  name:String
+  name:String,
+  artifact: Option<comm::Connection>,
 }
 
 /// Indicates what kind of output stream a logger will use.
-#[derive(Clone, PartialEq, Eq)]
+/// Outputs holding a sink, sender or callback are only equal to
+/// clones of themselves.
+#[derive(Clone)]
 pub enum LoggerOutput{
+  /// Log to a file, truncating it if it already exists.
   FileLog(PathBuf),
+  /// Log to a file, opened according to the given options.
+  FileLogWithOptions(PathBuf, FileOptions),
+  /// Log to a file, rotating it whenever it would grow past the
+  /// given number of bytes.  Older logs are kept at `path.1` (the
+  /// most recent) through `path.N`, where N is the number of old
+  /// files to retain.  An existing file at the path is appended to,
+  /// whatever the `append` option says.
+  RotatingFileLog(PathBuf, u64, usize, FileOptions),
+  /// Log to a file named by formatting the given path with the current
+  /// UTC time (strftime style, e.g. `app-%Y-%m-%d.log`), switching files
+  /// at every period boundary.  Files in the log directory which match
+  /// the pattern and are older than the given age are deleted.  As with
+  /// `RotatingFileLog`, files are always appended to.
+  #[cfg(feature = "time")]
+  TimeRotatingFileLog(PathBuf, RotationPeriod, Duration, FileOptions),
   StdoutLog,
   StderrLog,
   /// Log to various other loggers.
@@ -58,6 +87,197 @@ pub enum LoggerOutput{
   /// of this logger and the level of the sub loggers
   /// assigned to it.
   MultiLog(Vec<String>),
+  /// Log to the system log, either through the local syslog
+  /// socket or to a remote syslog server over UDP.  Fields are sent
+  /// as RFC 5424 structured data, or appended to RFC 3164 messages.
+  Syslog(SyslogOptions),
+  /// Log to the systemd journal, using the given string as the
+  /// SYSLOG_IDENTIFIER.  The logger's name is sent along in the
+  /// ARTIFACT_LOGGER field.
+  #[cfg(target_os = "linux")]
+  Journald(String),
+  /// Send newline terminated messages to a TCP or UDP endpoint,
+  /// such as a log collector.  If a TCP connection drops, messages
+  /// are held in a bounded backlog while reconnection is retried.
+  Network(NetworkOptions),
+  /// Keep the last N formatted messages in memory, where N is the
+  /// given capacity.  They can be retrieved with `Logger::snapshot`.
+  MemoryRing(usize),
+  /// Log to an application provided sink.
+  Custom(Arc<Mutex<LogSink>>),
+  /// Send each message, as a `LogRecord`, down the given channel.
+  Channel(Arc<Sender<LogRecord>>),
+  /// Call the given function with each message.  The function runs
+  /// on the logger task, so it should be quick.
+  Callback(Arc<Mutex<RecordCallback>>),
+}
+
+impl PartialEq for LoggerOutput {
+  fn eq(&self, other: &LoggerOutput) -> bool {
+    use self::LoggerOutput::*;
+
+    match (self, other) {
+      (FileLog(a), FileLog(b)) => a == b,
+      (FileLogWithOptions(a, a_options), FileLogWithOptions(b, b_options)) =>
+        a == b && a_options == b_options,
+      (RotatingFileLog(a, a_size, a_keep, a_options), RotatingFileLog(b, b_size, b_keep, b_options)) =>
+        a == b && a_size == b_size && a_keep == b_keep && a_options == b_options,
+      #[cfg(feature = "time")]
+      (TimeRotatingFileLog(a, a_period, a_age, a_options), TimeRotatingFileLog(b, b_period, b_age, b_options)) =>
+        a == b && a_period == b_period && a_age == b_age && a_options == b_options,
+      (StdoutLog, StdoutLog) => true,
+      (StderrLog, StderrLog) => true,
+      (MultiLog(a), MultiLog(b)) => a == b,
+      (Syslog(a), Syslog(b)) => a == b,
+      #[cfg(target_os = "linux")]
+      (Journald(a), Journald(b)) => a == b,
+      (Network(a), Network(b)) => a == b,
+      (MemoryRing(a), MemoryRing(b)) => a == b,
+      (Custom(a), Custom(b)) => Arc::ptr_eq(a, b),
+      (Channel(a), Channel(b)) => Arc::ptr_eq(a, b),
+      (Callback(a), Callback(b)) => Arc::ptr_eq(a, b),
+      _ => false
+    }
+  }
+}
+
+impl Eq for LoggerOutput {}
+
+/// What happens to log messages sent while the queue to the logger
+/// task is full.  See `ArtifactGlobalLib::init_bounded`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy{
+  /// Wait until there's room.
+  Block,
+  /// Drop the message being sent.
+  DropNewest,
+  /// Drop the oldest queued message to make room.
+  DropOldest,
+  /// Drop messages less severe than the given level, and wait
+  /// for room for the rest.
+  DropBelow(LogLevel),
+}
+
+/// What became of the log messages sent before artifact was shut down.
+/// See `ArtifactGlobalLib::shutdown`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShutdownReport{
+  /// Lines written to the loggers' outputs.  A message which
+  /// multi-loggers pass on to several outputs counts once for each,
+  /// and Artifact's own messages aren't counted.
+  pub written: u64,
+  /// Log messages which never reached the loggers, either because a
+  /// bounded queue was full or because they were still queued when
+  /// the deadline passed.
+  pub dropped: u64,
+  /// Loggers which failed to flush, or were still being flushed or
+  /// closed when the deadline passed.
+  pub failed_sinks: Vec<String>,
+  /// Whether the deadline passed before the logger task finished.
+  pub timed_out: bool,
+}
+
+/// Controls how a `FileLogWithOptions`, `RotatingFileLog` or
+/// `TimeRotatingFileLog` opens its files.
+/// The default truncates any existing file, creates no
+/// directories, and leaves permissions to the process umask.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileOptions{
+  /// Append to an existing file instead of truncating it.
+  pub append: bool,
+  /// Create any missing parent directories of the file.
+  pub create_dirs: bool,
+  /// Permission bits for the file if it has to be created.
+  /// Ignored on platforms other than Unix.
+  pub mode: Option<u32>,
+}
+
+/// How often a `TimeRotatingFileLog` switches to a new file.
+#[cfg(feature = "time")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RotationPeriod{
+  Hourly,
+  Daily,
+}
+
+#[cfg(feature = "time")]
+impl RotationPeriod{
+  /// Length of the period in seconds.
+  pub fn seconds(&self) -> i64 {
+    match *self {
+      RotationPeriod::Hourly => 60 * 60,
+      RotationPeriod::Daily => 24 * 60 * 60,
+    }
+  }
+}
+
+/// Where a `Syslog` logger sends its messages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyslogTransport{
+  /// A local Unix datagram socket, normally `/dev/log`.
+  #[cfg(unix)]
+  Unix(PathBuf),
+  /// A syslog server listening on UDP.
+  Udp(SocketAddr),
+}
+
+/// The syslog message format to use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyslogFormat{
+  /// The current syslog protocol.
+  Rfc5424,
+  /// The older BSD syslog format, for daemons which don't
+  /// understand RFC 5424.
+  Rfc3164,
+}
+
+/// Settings for a `Syslog` logger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyslogOptions{
+  pub transport: SyslogTransport,
+  pub format: SyslogFormat,
+  /// Facility code as defined by RFC 5424, e.g. 1 for user-level
+  /// messages, 3 for system daemons, or 16 through 23 for local0
+  /// through local7.
+  pub facility: u8,
+  /// Name of the application, used as the APP-NAME (or TAG) field.
+  pub app_name: String,
+}
+
+impl SyslogOptions{
+  /// Options for sending RFC 5424 messages with the user facility
+  /// to the given transport.
+  pub fn new(transport: SyslogTransport, app_name: &str) -> SyslogOptions {
+    SyslogOptions{
+      transport,
+      format: SyslogFormat::Rfc5424,
+      facility: 1,
+      app_name: app_name.to_string()}
+  }
+}
+
+/// Transport used by a `Network` logger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkProtocol{
+  Tcp,
+  Udp,
+}
+
+/// Settings for a `Network` logger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetworkOptions{
+  pub protocol: NetworkProtocol,
+  pub address: SocketAddr,
+  /// Maximum number of messages held while disconnected.  Once full,
+  /// the oldest messages are dropped to make room.
+  pub backlog: usize,
+}
+
+impl NetworkOptions{
+  /// Options for the given endpoint, with a backlog of 1024 messages.
+  pub fn new(protocol: NetworkProtocol, address: SocketAddr) -> NetworkOptions {
+    NetworkOptions{protocol, address, backlog: 1024}
+  }
 }
 
 impl Logger{
@@ -72,7 +292,13 @@ impl Logger{
   /// Creates a Logger instance, but does not tell the
   /// backend to initialize the logger.
   pub fn access(name: &str) -> Logger {
-    Logger{name: name.to_string()}
+    Logger{name: name.to_string(), artifact: None}
+  }
+
+  /// As `access`, but for a logger of the given `Artifact` rather
+  /// than of the global one.
+  pub fn access_in(artifact: &Artifact, name: &str) -> Logger {
+    Logger{name: name.to_string(), artifact: Some(artifact.connection.clone())}
   }
 
   /// Accesses the Artifact library's internal logger.
@@ -92,16 +318,32 @@ impl Logger{
   /// Creates a logger for the given output which logs messages at or above the given level.
   /// This also initializes the logger by telling the backend task.
   pub fn new_with_level(name: &str, ty: LoggerOutput, level:LogLevel) -> Logger {
-    send_logger_message(LoggerMessage::NewLogger(name.to_string(),
-                                                 level,
-                                                 ty));
-    Logger::access(name)
+    Logger::access(name).init(ty, level)
+  }
+
+  /// As `new`, but creates the logger in the given `Artifact` rather
+  /// than in the global one.
+  pub fn new_in(artifact: &Artifact, name: &str, ty: LoggerOutput) -> Logger {
+    Logger::new_with_level_in(artifact, name, ty, level::DEFAULT)
+  }
+
+  /// As `new_with_level`, but creates the logger in the given
+  /// `Artifact` rather than in the global one.
+  pub fn new_with_level_in(artifact: &Artifact, name: &str, ty: LoggerOutput, level: LogLevel) -> Logger {
+    Logger::access_in(artifact, name).init(ty, level)
+  }
+
+  fn init(self, ty: LoggerOutput, level: LogLevel) -> Logger {
+    self.send(LoggerMessage::NewLogger(self.name.to_string(),
+                                       level,
+                                       ty));
+    self
   }
 
   /// Redirects a logger to a new output location.
   /// Returns the logger as well
   pub fn redirect(&self, ty: LoggerOutput) {
-    send_logger_message(
+    self.send(
       LoggerMessage::RedirectLogger(
         self.name.to_string(),
         None,
@@ -111,7 +353,7 @@ impl Logger{
   /// Redirects a logger and changes its level
   /// Returns the logger
   pub fn redirect_set_level(&self, ty: LoggerOutput, level: LogLevel) {
-    send_logger_message(
+    self.send(
       LoggerMessage::RedirectLogger(
         self.name.to_string(),
         Some(level),
@@ -121,19 +363,19 @@ impl Logger{
   ///Prevents use of a logger name, and kills off any existing
   ///logger instances with that name
   pub fn disable(self) {
-    send_logger_message(LoggerMessage::Disable(self.name, true));
+    self.send(LoggerMessage::Disable(self.name.clone(), true));
   }
 
   ///Prevents use of a logger name, kills off any existing loggers
   ///with that name, and disables logging info about that logger
   ///entirely.
   pub fn disable_without_logs(self) {
-    send_logger_message(LoggerMessage::Disable(self.name, false));
+    self.send(LoggerMessage::Disable(self.name.clone(), false));
   }
 
   /// Sets the default logger to be this logger.
   pub fn set_as_default(&self) {
-    send_logger_message(
+    self.send(
       LoggerMessage::SetDefaultLogTarget(
         DefaultLogTarget::LogToTarget(self.name.to_string())))
   }
@@ -143,66 +385,224 @@ impl Logger{
   /// to this logger.  However, no addition to the message will be made
   /// by this logger.
   pub fn set_as_silent_default(&self) {
-    send_logger_message(
+    self.send(
       LoggerMessage::SetDefaultLogTarget(
         DefaultLogTarget::LogToTargetNoIndicator(self.name.to_string())))
   }
 
   ///Sets the logger's format
   pub fn set_format(&self, formatter: Box<MessageFormatter>) {
-    send_logger_message(LoggerMessage::SetFormatter(self.name.to_string(), formatter));
+    self.send(LoggerMessage::SetFormatter(self.name.to_string(), formatter));
+  }
+
+  /// Retrieves the messages currently held by a `MemoryRing` logger,
+  /// oldest first.  This waits for the logger task to reply, so any
+  /// messages sent from this thread beforehand are included.
+  /// Loggers with any other kind of output return no messages.
+  pub fn snapshot(&self) -> Vec<String> {
+    let (tx, rx) = channel();
+    self.send(LoggerMessage::Snapshot(self.name.clone(), tx));
+    rx.recv().unwrap_or_default()
+  }
+
+  /// Waits until every message sent before from this thread has been
+  /// written, and the outputs this logger writes to have been flushed.
+  pub fn flush(&self) {
+    self.flush_outputs(false);
+  }
+
+  /// As `flush`, but also waits for log files to be written to disk.
+  pub fn flush_and_sync(&self) {
+    self.flush_outputs(true);
+  }
+
+  fn flush_outputs(&self, sync: bool) {
+    let (tx, rx) = channel();
+    self.send(LoggerMessage::Flush(Some(self.name.clone()), sync, tx));
+    let _ = rx.recv();
   }
 
   /// Creates a new log message.  This just sends a message across
   /// the backend channel to the actual logger task.
+  #[inline]
   pub fn log(&self, level: LogLevel, message:&str){
-    send_logger_message(
-      LoggerMessage::LogMessage(
-        self.name.clone(),
+    if !level::static_enabled(level) {
+      return;
+    }
+    self.send_log_entry(level, || {
+      LogEntry::new(
+        &self.name,
         level,
-        message.to_string()));
+        message.to_string(),
+        Vec::new())
+    });
+  }
+
+  /// Whether a message at the given level would be written anywhere,
+  /// taking multi-loggers and the default logger into account.  This is
+  /// usually answered without involving the logger task.
+  #[inline]
+  pub fn is_enabled(&self, level: LogLevel) -> bool {
+    level::static_enabled(level) && self.is_log_enabled(level)
+  }
+
+  /// Creates a new log message from a closure, which is only called if
+  /// the message would be written:
+  /// `logger.log_with(level::DEBUG, || format!("state: {:?}", state))`.
+  #[inline]
+  pub fn log_with<F>(&self, level: LogLevel, message: F) where F: FnOnce() -> String {
+    if !level::static_enabled(level) {
+      return;
+    }
+    self.send_log_entry(level, || LogEntry::new(&self.name, level, message(), Vec::new()));
   }
 
+  /// Creates a new log message, recording where in the source it was
+  /// logged from.  Usually called through the `artifact_*!` macros, e.g.
+  /// `artifact_info!(logger, "x = {}", x)`, which only format the
+  /// message if it would be written.
+  #[inline]
+  pub fn log_at(&self, level: LogLevel, location: Location, message: fmt::Arguments){
+    if !level::static_enabled(level) {
+      return;
+    }
+    self.send_log_entry(level, || {
+      LogEntry::new(
+        &self.name,
+        level,
+        message.to_string(),
+        Vec::new()).with_location(location)
+    });
+  }
+
+  /// Creates a new log message with key-value fields attached, e.g.
+  /// `logger.log_kv(level::INFO, "request done", &[("status", 200), ("ms", 14)])`.
+  /// Fields of different types can be mixed by giving `FieldValue`s.
+  #[inline]
+  pub fn log_kv<V>(&self, level: LogLevel, message: &str, fields: &[(&str, V)])
+      where V: Into<FieldValue> + Clone {
+    if !level::static_enabled(level) {
+      return;
+    }
+    self.send_log_entry(level, || {
+      LogEntry::new(
+        &self.name,
+        level,
+        message.to_string(),
+        fields.iter().map(|&(key, ref value)| Field::new(key, value.clone())).collect())
+    });
+  }
+
+  #[inline]
   pub fn wtf(&self, message:&str){
     self.log(level::WTF, message);
   }
 
+  #[inline]
   pub fn critical(&self, message:&str){
     self.log(level::CRITICAL, message);
   }
 
+  #[inline]
   pub fn severe(&self, message:&str){
     self.log(level::SEVERE, message);
   }
 
+  #[inline]
   pub fn warning(&self, message:&str){
     self.log(level::WARNING, message)
   }
 
+  #[inline]
   pub fn debug(&self, message:&str){
     self.log(level::DEBUG, message);
   }
 
+  #[inline]
   pub fn info(&self, message:&str){
     self.log(level::INFO, message);
   }
 
+  #[inline]
   pub fn trace(&self, message:&str) {
     self.log(level::TRACE, message);
   }
 
+  #[inline]
   pub fn verbose(&self, message:&str){
     self.log(level::VERBOSE, message);
   }
+
+  #[inline]
+  pub fn wtf_kv<V: Into<FieldValue> + Clone>(&self, message:&str, fields: &[(&str, V)]){
+    self.log_kv(level::WTF, message, fields);
+  }
+
+  #[inline]
+  pub fn critical_kv<V: Into<FieldValue> + Clone>(&self, message:&str, fields: &[(&str, V)]){
+    self.log_kv(level::CRITICAL, message, fields);
+  }
+
+  #[inline]
+  pub fn severe_kv<V: Into<FieldValue> + Clone>(&self, message:&str, fields: &[(&str, V)]){
+    self.log_kv(level::SEVERE, message, fields);
+  }
+
+  #[inline]
+  pub fn warning_kv<V: Into<FieldValue> + Clone>(&self, message:&str, fields: &[(&str, V)]){
+    self.log_kv(level::WARNING, message, fields);
+  }
+
+  #[inline]
+  pub fn debug_kv<V: Into<FieldValue> + Clone>(&self, message:&str, fields: &[(&str, V)]){
+    self.log_kv(level::DEBUG, message, fields);
+  }
+
+  #[inline]
+  pub fn info_kv<V: Into<FieldValue> + Clone>(&self, message:&str, fields: &[(&str, V)]){
+    self.log_kv(level::INFO, message, fields);
+  }
+
+  #[inline]
+  pub fn trace_kv<V: Into<FieldValue> + Clone>(&self, message:&str, fields: &[(&str, V)]){
+    self.log_kv(level::TRACE, message, fields);
+  }
+
+  #[inline]
+  pub fn verbose_kv<V: Into<FieldValue> + Clone>(&self, message:&str, fields: &[(&str, V)]){
+    self.log_kv(level::VERBOSE, message, fields);
+  }
+
+  fn send(&self, message: LoggerMessage) {
+    match self.artifact {
+      Some(ref connection) => connection.send(message),
+      None => send_logger_message(message)
+    }
+  }
+
+  #[inline]
+  fn send_log_entry<F>(&self, level: LogLevel, make_entry: F) where F: FnOnce() -> LogEntry {
+    match self.artifact {
+      Some(ref connection) => connection.send_log_entry(&self.name, level, make_entry),
+      None => comm::send_log_entry(&self.name, level, make_entry)
+    }
+  }
+
+  fn is_log_enabled(&self, level: LogLevel) -> bool {
+    match self.artifact {
+      Some(ref connection) => connection.is_log_enabled(&self.name, level),
+      None => comm::is_log_enabled(&self.name, level)
+    }
+  }
 }
 
 
 #[cfg(feature = "log")]
 mod logsupport{
-  use internal::task::LoggerMessage;
-  use std::sync::mpsc::channel;
-  use internal::comm::send_logger_message;
+  use internal::task::LogEntry;
+  use internal::comm::{send_log_entry, is_log_enabled};
   use level;
+  use location::Location;
 
   use log;
 
@@ -210,30 +610,31 @@ mod logsupport{
 
   impl log::Log for ArtifactDelegateLog {
     fn enabled(&self, metadata: &log::LogMetadata) -> bool {
-      //TODO can we make this work better
-      let (tx, rx) = channel();
-
-      send_logger_message(
-        LoggerMessage::IsLogEnabled(
-          metadata.target().to_owned(),
-          to_internal_level(metadata.level()),
-          tx));
-
-      match rx.recv() {
-        Ok(rval) => rval,
-        _ => false
-      }
+      let level = to_internal_level(metadata.level());
+      level::static_enabled(level) && is_log_enabled(metadata.target(), level)
     }
 
     fn log(&self, record:&log::LogRecord){
-      send_logger_message(
-        LoggerMessage::LogMessage(
-          record.target().to_owned(),
-          to_internal_level(record.level()),
-          format!("{}", record.args())));
+      let level = to_internal_level(record.level());
+      if !level::static_enabled(level) {
+        return;
+      }
+      send_log_entry(record.target(), level, || {
+        LogEntry::new(
+          record.target(),
+          level,
+          format!("{}", record.args()),
+          Vec::new()).with_location(to_location(record.location()))
+      });
     }
   }
 
+  fn to_location(location: &log::LogLocation) -> Location {
+    // The accessors only give the strings out with the record's lifetime,
+    // but the fields themselves are always static.
+    Location::new(location.__file, location.__line, location.__module_path)
+  }
+
   #[cfg(feature = "log")]
   fn to_internal_level(lvl: log::LogLevel) -> level::LogLevel {
     use log::LogLevel::*;
@@ -247,3 +648,60 @@ mod logsupport{
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+  use std::sync::{Arc, Mutex};
+  use std::sync::mpsc::channel;
+
+  use level;
+  use level::LogLevel;
+  use sink::LogSink;
+  use Artifact;
+  use super::{Logger, LoggerOutput, FileOptions, INTERNAL_LOGGER_NAME};
+
+  struct NullSink;
+
+  impl LogSink for NullSink {
+    fn write(&mut self, _: &str, _: LogLevel, _: &str) {}
+  }
+
+  #[test]
+  fn outputs_with_the_same_settings_are_equal() {
+    assert!(LoggerOutput::FileLog(PathBuf::from("a.log")) == LoggerOutput::FileLog(PathBuf::from("a.log")));
+    assert!(LoggerOutput::FileLog(PathBuf::from("a.log")) != LoggerOutput::FileLog(PathBuf::from("b.log")));
+    assert!(LoggerOutput::FileLog(PathBuf::from("a.log"))
+            != LoggerOutput::FileLogWithOptions(PathBuf::from("a.log"), FileOptions::default()));
+    assert!(LoggerOutput::StdoutLog != LoggerOutput::StderrLog);
+  }
+
+  #[test]
+  fn shared_outputs_are_only_equal_to_clones() {
+    let sink = LoggerOutput::Custom(Arc::new(Mutex::new(NullSink)));
+    assert!(sink.clone() == sink);
+    assert!(LoggerOutput::Custom(Arc::new(Mutex::new(NullSink))) != sink);
+
+    let (tx, _rx) = channel();
+    let sender = LoggerOutput::Channel(Arc::new(tx.clone()));
+    assert!(sender.clone() == sender);
+    assert!(LoggerOutput::Channel(Arc::new(tx)) != sender);
+  }
+
+  #[test]
+  fn lazy_messages_to_missing_loggers_are_reported() {
+    let artifact = Artifact::new();
+    let internal = Logger::access_in(&artifact, INTERNAL_LOGGER_NAME);
+    internal.redirect(LoggerOutput::MemoryRing(10));
+    let missing = Logger::access_in(&artifact, "Missing");
+
+    missing.log_with(level::INFO, || "from a closure".to_string());
+    ::artifact_info!(missing, "from the {}", "macro");
+    artifact.flush();
+
+    let reported = internal.snapshot().iter()
+      .filter(|line| line.contains("Can't log to the Missing logger, it doesn't exist."))
+      .count();
+    assert_eq!(reported, 2);
+  }
+}
@@ -0,0 +1,414 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+//! A formatter configured with a template string instead of code.
+
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "time")]
+use time;
+
+use field;
+use format::{MessageFormatter, MessageInfo, Forwarding};
+
+/// Formats messages according to a template, such as
+/// `"{time:%Y-%m-%d %H:%M:%S} {level:<8} [{logger}] {message}"`.
+/// The template is parsed once, when the formatter is created.
+///
+/// The placeholders are:
+///
+/// * `{time}` - the local time, formatted with the strftime string after
+///   the colon (`%Y-%m-%d %H:%M:%S` if none is given).  Needs the `time`
+///   feature.
+/// * `{level}` - the level's name, e.g. `WARNING`.
+/// * `{level_value}` - the level's number, e.g. `400`.
+/// * `{logger}` - the name of the logger the message was logged to.
+/// * `{message}` - the message itself.
+/// * `{fields}` - any fields attached to the message, as `key=value` pairs.
+/// * `{thread}` - the name (or id, if unnamed) of the thread which logged
+///   the message.
+/// * `{forwarded_from}` - the multi-logger which forwarded the message,
+///   or the missing logger it was sent to if it ended up at the default
+///   logger.  Empty if it wasn't forwarded.
+/// * `{file}`, `{line}`, `{module}` - where the message was logged from.
+///   Only messages logged with the `artifact_*!` macros have a location,
+///   for others these are empty.
+///
+/// All placeholders except `{time}` take a width and alignment after a
+/// colon, as with `format!`: `{level:<8}`, `{logger:>12}`, `{thread:^10}`,
+/// or with a fill character, `{level:*<8}`.  Use `{{` and `}}` for
+/// literal braces.
+///
+/// Forwarding is only shown through `{forwarded_from}`, nothing is
+/// added to the message by multi-loggers or the default logger.
+pub struct PatternFormatter{
+  segments: Vec<Segment>,
+}
+
+/// Returned when a template can't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternError{
+  /// Character offset in the template where the problem was found.
+  pub position: usize,
+  pub reason: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Align{
+  Left,
+  Right,
+  Center,
+}
+
+#[derive(Clone, Copy)]
+struct Padding{
+  fill: char,
+  align: Align,
+  width: usize,
+}
+
+enum Item{
+  Level,
+  LevelValue,
+  Logger,
+  Message,
+  Fields,
+  Thread,
+  ForwardedFrom,
+  File,
+  Line,
+  Module,
+}
+
+enum Segment{
+  Literal(String),
+  #[cfg(feature = "time")]
+  Time(String),
+  Placeholder(Item, Option<Padding>),
+}
+
+impl PatternFormatter{
+  pub fn new(template: &str) -> Result<PatternFormatter, PatternError> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().enumerate().peekable();
+
+    while let Some((pos, c)) = chars.next() {
+      match c {
+        '{' if chars.peek().map(|&(_, next)| next) == Some('{') => {
+          chars.next();
+          literal.push('{');
+        }
+        '}' if chars.peek().map(|&(_, next)| next) == Some('}') => {
+          chars.next();
+          literal.push('}');
+        }
+        '{' => {
+          let mut placeholder = String::new();
+          loop {
+            match chars.next() {
+              Some((_, '}')) => break,
+              Some((_, c)) => placeholder.push(c),
+              None => return Err(PatternError::new(pos, "unclosed placeholder")),
+            }
+          }
+          if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+            literal = String::new();
+          }
+          segments.push(parse_placeholder(pos, &placeholder)?);
+        }
+        '}' => return Err(PatternError::new(pos, "unmatched '}', use '}}' for a literal brace")),
+        c => literal.push(c),
+      }
+    }
+
+    if !literal.is_empty() {
+      segments.push(Segment::Literal(literal));
+    }
+    Ok(PatternFormatter{segments})
+  }
+}
+
+impl PatternError{
+  fn new(position: usize, reason: &str) -> PatternError {
+    PatternError{position, reason: reason.to_string()}
+  }
+}
+
+impl fmt::Display for PatternError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "invalid pattern at offset {}: {}", self.position, self.reason)
+  }
+}
+
+impl Error for PatternError {}
+
+fn parse_placeholder(pos: usize, placeholder: &str) -> Result<Segment, PatternError> {
+  let (name, spec) = match placeholder.find(':') {
+    Some(idx) => (&placeholder[..idx], Some(&placeholder[idx + 1..])),
+    None => (placeholder, None)
+  };
+
+  let item = match name.trim() {
+    "time" => return parse_time(pos, spec),
+    "level" => Item::Level,
+    "level_value" => Item::LevelValue,
+    "logger" => Item::Logger,
+    "message" => Item::Message,
+    "fields" => Item::Fields,
+    "thread" => Item::Thread,
+    "forwarded_from" => Item::ForwardedFrom,
+    "file" => Item::File,
+    "line" => Item::Line,
+    "module" => Item::Module,
+    other => return Err(PatternError::new(pos, &format!("unknown placeholder '{}'", other))),
+  };
+
+  let padding = match spec {
+    Some(spec) => Some(parse_padding(pos, spec)?),
+    None => None
+  };
+  Ok(Segment::Placeholder(item, padding))
+}
+
+#[cfg(feature = "time")]
+fn parse_time(pos: usize, spec: Option<&str>) -> Result<Segment, PatternError> {
+  let time_format = spec.unwrap_or("%Y-%m-%d %H:%M:%S");
+  // Check the format now, rather than failing on every message.
+  match time::strftime(time_format, &time::now()) {
+    Ok(_) => Ok(Segment::Time(time_format.to_string())),
+    Err(e) => Err(PatternError::new(pos, &format!("bad time format: {}", e))),
+  }
+}
+
+#[cfg(not(feature = "time"))]
+fn parse_time(pos: usize, _: Option<&str>) -> Result<Segment, PatternError> {
+  Err(PatternError::new(pos, "the time placeholder needs the time feature"))
+}
+
+/// Parses `[[fill]align]width`, where align is one of `<`, `>` or `^`.
+fn parse_padding(pos: usize, spec: &str) -> Result<Padding, PatternError> {
+  let chars: Vec<char> = spec.chars().collect();
+  let align_of = |c: char| match c {
+    '<' => Some(Align::Left),
+    '>' => Some(Align::Right),
+    '^' => Some(Align::Center),
+    _ => None
+  };
+
+  let (fill, align, rest) =
+    if chars.len() >= 2 && align_of(chars[1]).is_some() {
+      (chars[0], align_of(chars[1]), &chars[2..])
+    } else if !chars.is_empty() && align_of(chars[0]).is_some() {
+      (' ', align_of(chars[0]), &chars[1..])
+    } else {
+      (' ', None, &chars[..])
+    };
+
+  let width_str: String = rest.iter().cloned().collect();
+  let width = match width_str.parse::<usize>() {
+    Ok(width) => width,
+    Err(_) if width_str.is_empty() => 0,
+    Err(_) => return Err(PatternError::new(pos, &format!("bad width '{}'", width_str))),
+  };
+
+  Ok(Padding{fill, align: align.unwrap_or(Align::Left), width})
+}
+
+fn push_padded(out: &mut String, value: &str, padding: Option<Padding>) {
+  let padding = match padding {
+    Some(padding) => padding,
+    None => {
+      out.push_str(value);
+      return;
+    }
+  };
+
+  let len = visible_len(value);
+  let total = padding.width.saturating_sub(len);
+  let (before, after) = match padding.align {
+    Align::Left => (0, total),
+    Align::Right => (total, 0),
+    Align::Center => (total / 2, total - total / 2),
+  };
+  out.extend((0..before).map(|_| padding.fill));
+  out.push_str(value);
+  out.extend((0..after).map(|_| padding.fill));
+}
+
+/// Counts characters, leaving out ANSI colour sequences so that coloured
+/// level strings still line up.
+fn visible_len(value: &str) -> usize {
+  let mut len = 0;
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    if c == '\x1b' {
+      for c in chars.by_ref() {
+        if c == 'm' {
+          break;
+        }
+      }
+    } else {
+      len += 1;
+    }
+  }
+  len
+}
+
+impl MessageFormatter for PatternFormatter {
+  fn format_message(&self, logger_name: &str, level_string: &str, message: &str) -> String {
+    // No numeric level is available here.
+    self.format_message_info(
+      &MessageInfo{
+        logger_name,
+        level: 0,
+        level_string,
+        message,
+        fields: &[],
+        thread: None,
+        forwarded: &[],
+        to_terminal: false,
+        location: None})
+  }
+
+  fn format_message_info(&self, info: &MessageInfo) -> String {
+    let mut out = String::new();
+    for segment in &self.segments {
+      match *segment {
+        Segment::Literal(ref literal) => out.push_str(literal),
+        #[cfg(feature = "time")]
+        Segment::Time(ref time_format) =>
+          out.push_str(&time::strftime(time_format, &time::now()).unwrap_or_default()),
+        Segment::Placeholder(ref item, padding) => {
+          let forwarded_from = match info.forwarded.last() {
+            Some(&Forwarding::Multi{from, ..}) | Some(&Forwarding::Default{from, ..}) => from,
+            None => ""
+          };
+          match *item {
+            Item::Level => push_padded(&mut out, info.level_string, padding),
+            Item::LevelValue => push_padded(&mut out, &info.level.to_string(), padding),
+            Item::Logger => push_padded(&mut out, info.logger_name, padding),
+            Item::Message => push_padded(&mut out, info.message, padding),
+            Item::Fields =>
+              push_padded(&mut out, field::append_fields("", info.fields).trim_start(), padding),
+            Item::Thread => push_padded(&mut out, info.thread.unwrap_or(""), padding),
+            Item::ForwardedFrom => push_padded(&mut out, forwarded_from, padding),
+            Item::File => push_padded(&mut out, info.location.map_or("", |l| l.file), padding),
+            Item::Line =>
+              push_padded(&mut out, &info.location.map_or(String::new(), |l| l.line.to_string()), padding),
+            Item::Module => push_padded(&mut out, info.location.map_or("", |l| l.module_path), padding),
+          }
+        }
+      }
+    }
+    out
+  }
+
+  fn add_logger_name_to_multi_message(&self, _: &str, msg: &str) -> String {
+    msg.to_string()
+  }
+
+  fn add_defaulting_name_to_message(&self, _: &str, msg: &str) -> String {
+    msg.to_string()
+  }
+
+  fn shows_forwarding(&self) -> bool {
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use field::Field;
+  use format::{MessageFormatter, MessageInfo, Forwarding};
+  use location::Location;
+  use super::{PatternFormatter, PatternError, visible_len};
+
+  fn format(template: &str) -> String {
+    let fields = vec![Field::new("status", 200), Field::new("path", "/a b")];
+    let forwarded = [Forwarding::Multi{from: "Multi", to: "Foo"}];
+    let location = Location::new("src/main.rs", 12, "app::main");
+    PatternFormatter::new(template).unwrap().format_message_info(
+      &MessageInfo{
+        logger_name: "Foo",
+        level: 400,
+        level_string: "WARNING",
+        message: "Disk is full",
+        fields: &fields,
+        thread: Some("worker"),
+        forwarded: &forwarded,
+        to_terminal: false,
+        location: Some(&location)})
+  }
+
+  fn error(template: &str) -> PatternError {
+    match PatternFormatter::new(template) {
+      Ok(_) => panic!("{} should not parse", template),
+      Err(e) => e
+    }
+  }
+
+  #[test]
+  fn placeholders_are_filled_in() {
+    assert_eq!(format("[{logger}] {level} ({level_value}): {message}"), "[Foo] WARNING (400): Disk is full");
+    assert_eq!(format("{fields}"), r#"status=200 path="/a b""#);
+    assert_eq!(format("{thread} via {forwarded_from}"), "worker via Multi");
+    assert_eq!(format("{file}:{line} in {module}"), "src/main.rs:12 in app::main");
+  }
+
+  #[test]
+  fn braces_can_be_escaped() {
+    assert_eq!(format("{{x}} {{{logger}}}"), "{x} {Foo}");
+  }
+
+  #[test]
+  fn placeholders_are_padded() {
+    assert_eq!(format("{level:<9}|"), "WARNING  |");
+    assert_eq!(format("{level:>9}|"), "  WARNING|");
+    assert_eq!(format("{logger:*^8}|"), "**Foo***|");
+    assert_eq!(format("{logger:5}|"), "Foo  |");
+    assert_eq!(format("{message:3}"), "Disk is full");
+  }
+
+  #[test]
+  fn colour_codes_do_not_count_towards_width() {
+    assert_eq!(visible_len("\x1b[1;31mWTF\x1b[0m"), 3);
+  }
+
+  #[test]
+  fn bad_templates_are_rejected() {
+    assert_eq!(error("{nope}"), PatternError{position: 0, reason: "unknown placeholder 'nope'".to_string()});
+    assert_eq!(error("ab {level").position, 3);
+    assert_eq!(error("a } b").position, 2);
+    assert_eq!(error("{level:<x}").reason, "bad width 'x'");
+  }
+
+  #[cfg(feature = "time")]
+  #[test]
+  fn time_format_is_checked_up_front() {
+    assert!(PatternFormatter::new("{time:%Y}").is_ok());
+    assert!(PatternFormatter::new("{time:%Q}").is_err());
+  }
+}
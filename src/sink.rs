@@ -0,0 +1,72 @@
+/*
+ * Copyright (c) 2015 Brandon Sanderson
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in
+ * all copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+ * THE SOFTWARE.
+ *
+ */
+
+use level::LogLevel;
+use field::Field;
+use location::Location;
+
+/// A message delivered by a `Channel` or `Callback` logger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogRecord{
+  /// Name of the logger the message was delivered by.
+  pub logger: String,
+  pub level: LogLevel,
+  /// The name registered for the level, e.g. "WARNING".
+  pub level_string: String,
+  /// The message, as formatted by the logger's formatter.
+  pub message: String,
+  /// Fields attached to the message.
+  pub fields: Vec<Field>,
+  /// Where the message was logged from, if it was logged with one of
+  /// the `artifact_*!` macros.
+  pub location: Option<Location>,
+}
+
+/// A function given each message by a `Callback` logger.
+pub type RecordCallback = FnMut(&LogRecord) + Send;
+
+/// A destination for log messages, for outputs which Artifact
+/// doesn't provide itself.  Give one to a logger with
+/// `LoggerOutput::Custom`.  All calls to the sink are made by the
+/// logger task.  The same sink can be given to several loggers.
+pub trait LogSink : Send {
+  /// Write a message.  The message has already been formatted by
+  /// the logger's formatter.
+  fn write(&mut self, logger_name: &str, level: LogLevel, message: &str);
+
+  /// Write a message which has fields attached.  Sinks which can store
+  /// structured data should override this, by default the fields are
+  /// ignored and the message is passed on to `write`.
+  fn write_with_fields(&mut self, logger_name: &str, level: LogLevel, message: &str, fields: &[Field]) {
+    let _ = fields;
+    self.write(logger_name, level, message);
+  }
+
+  /// Push out anything the sink has buffered.
+  fn flush(&mut self) {}
+
+  /// Called once the last logger using the sink is removed, either by
+  /// being redirected or disabled, or because the logger task is stopping.
+  /// The sink is flushed first and is never used again afterwards.
+  fn close(&mut self) {}
+}